@@ -0,0 +1,156 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::Value;
+
+/// Example workspaces bundled as templates for `tsg new`, by template name.
+const TEMPLATES: [(&str, &str); 4] = [
+    ("basic", "examples/basic/one_page_md"),
+    ("blog", "examples/blog"),
+    ("l18n", "examples/l18n/basic"),
+    ("bootstrap", "examples/bootstrap"),
+];
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let mut code = String::from("pub static TEMPLATES: &[Template] = &[\n");
+    for (name, dir) in TEMPLATES {
+        println!("cargo:rerun-if-changed={}", dir);
+        let root = manifest_dir.join(dir);
+        let mut files = Vec::new();
+        list_files(&root, &mut files);
+        files.sort();
+        code.push_str(&format!("    ({:?}, &[\n", name));
+        for file in files {
+            let path: Vec<_> = file
+                .strip_prefix(&root)
+                .unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            code.push_str(&format!(
+                "        ({:?}, include_bytes!({:?})),\n",
+                path.join("/"),
+                file
+            ));
+        }
+        code.push_str("    ]),\n");
+    }
+    code.push_str("];\n");
+
+    fs::write(out_dir.join("templates.rs"), code).unwrap();
+
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let mut code = String::from("pub static DEPENDENCIES: &[Dependency] = &[\n");
+    for (name, version, license) in dependencies(&manifest_dir) {
+        code.push_str(&format!(
+            "    Dependency {{ name: {:?}, version: {:?}, license: {:?} }},\n",
+            name, version, license
+        ));
+    }
+    code.push_str("];\n");
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|name| name.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    code.push_str(&format!("pub static FEATURES: &[&str] = &{:?};\n", features));
+
+    fs::write(out_dir.join("dependencies.rs"), code).unwrap();
+}
+
+/// Name, version and license of all packages compiled into tsg for the current target,
+/// sorted by name. Empty in case cargo can't provide the metadata.
+fn dependencies(manifest_dir: &Path) -> Vec<(String, String, String)> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
+    let target = env::var("TARGET").unwrap();
+    let output = Command::new(cargo)
+        .args(["metadata", "--format-version", "1", "--offline", "--filter-platform"])
+        .arg(target)
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("Cargo.toml"))
+        .output();
+    let metadata: Value = match output {
+        Ok(output) if output.status.success() => serde_json::from_slice(&output.stdout).unwrap(),
+        _ => {
+            println!("cargo:warning=cargo metadata failed, `tsg env -v` won't list dependencies");
+            return Vec::new();
+        }
+    };
+
+    let resolve = &metadata["resolve"];
+    let nodes: HashMap<&str, &Value> = resolve["nodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|node| (node["id"].as_str().unwrap(), node))
+        .collect();
+    // proc macros only run while compiling, and aren't embedded either
+    let proc_macros: HashSet<&str> = metadata["packages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|package| {
+            package["targets"].as_array().unwrap().iter().any(|target| {
+                target["kind"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .any(|kind| kind == "proc-macro")
+            })
+        })
+        .map(|package| package["id"].as_str().unwrap())
+        .collect();
+    // walk the normal dependencies only, build and dev dependencies aren't embedded
+    let root = resolve["root"].as_str().unwrap();
+    let mut seen = HashSet::new();
+    let mut todo = vec![root];
+    while let Some(id) = todo.pop() {
+        for dep in nodes[id]["deps"].as_array().unwrap() {
+            let normal = dep["dep_kinds"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|kind| kind["kind"].is_null());
+            let dep_id = dep["pkg"].as_str().unwrap();
+            if normal && !proc_macros.contains(dep_id) && seen.insert(dep_id) {
+                todo.push(dep_id);
+            }
+        }
+    }
+
+    let mut dependencies: Vec<_> = metadata["packages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|package| seen.contains(package["id"].as_str().unwrap()))
+        .map(|package| {
+            let field = |key: &str| package[key].as_str().unwrap_or("unknown").to_owned();
+            (field("name"), field("version"), field("license"))
+        })
+        .collect();
+    dependencies.sort();
+    dependencies
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            // skip the output and cache of examples built locally
+            if path.file_name().map(|n| n != "publish" && n != ".tsg").unwrap_or(false) {
+                list_files(&path, files);
+            }
+        } else if path.file_name().map(|n| n != "README.md").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1 @@
+printf '{"os": "%s", "arch": "%s"}\n' "$(uname -s)" "$(uname -m)"
@@ -0,0 +1,44 @@
+/// Version of tsg.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A package compiled into tsg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// SPDX license expression, as declared by the package.
+    pub license: &'static str,
+}
+
+mod generated {
+    use super::Dependency;
+
+    include!(concat!(env!("OUT_DIR"), "/dependencies.rs"));
+}
+
+/// All packages compiled into tsg, directly or indirectly, sorted by name.
+/// Build and development dependencies aren't included.
+pub fn dependencies() -> &'static [Dependency] {
+    generated::DEPENDENCIES
+}
+
+/// Cargo features enabled when building tsg.
+pub fn features() -> &'static [&'static str] {
+    generated::FEATURES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies() {
+        let rhai = dependencies().iter().find(|d| d.name == "rhai").unwrap();
+        assert!(rhai.version.starts_with("1."));
+        assert_eq!(rhai.license, "MIT OR Apache-2.0");
+        assert!(dependencies().iter().all(|d| d.name != "tsg"));
+        // proc macros, and the crates only they depend on, aren't compiled into tsg
+        assert!(dependencies().iter().all(|d| d.name != "clap_derive" && d.name != "syn"));
+        assert!(dependencies().windows(2).all(|w| w[0].name <= w[1].name));
+    }
+}
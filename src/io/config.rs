@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use super::{yaml, FileLocale, FileVariant, Value, VariantDimension};
+
+/// Name of the optional site configuration file, found at the root of a workspace.
+pub const CONFIG_FILE: &str = "tsg.yml";
+
+/// Site configuration, as declared in the `tsg.yml` file of a workspace.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    // locales to render pages in, instead of all locales found in the workspace
+    locales: Option<Vec<FileLocale>>,
+    // locales to fall back to, by locale, overriding the default fallbacks
+    fallbacks: BTreeMap<FileLocale, Vec<FileLocale>>,
+    // dimensions files can vary in besides their locale, in the order of their file name suffixes
+    dimensions: Vec<VariantDimension>,
+}
+
+impl Config {
+    /// Read the configuration of the workspace at the given root,
+    /// using the default configuration in case it has none.
+    pub fn read<P: AsRef<Path>>(root: P) -> Result<Config> {
+        let path = root.as_ref().join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        Config::parse(&content).with_context(|| format!("read {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Config> {
+        let value = yaml::parse(content)?;
+        let mut config = Config::default();
+        let map = match value {
+            Value::Null => return Ok(config),
+            Value::Mapping(map) => map,
+            _ => return Err(anyhow!("configuration must be a mapping")),
+        };
+        for (key, value) in map {
+            match key.as_str() {
+                "locales" => config.locales = Some(parse_locales(&value).context("locales")?),
+                "fallbacks" => config.fallbacks = parse_fallbacks(value)?,
+                "variants" => config.dimensions = parse_dimensions(value)?,
+                _ => return Err(anyhow!("unknown configuration '{}'", key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Locales to render every page in, besides rendering it without a locale,
+    /// in case the site declares them rather than using all locales found in its files.
+    pub fn locales(&self) -> Option<&[FileLocale]> {
+        self.locales.as_deref()
+    }
+
+    /// Whether the site declares a locale of the given language, which is required
+    /// for languages that aren't two-letter codes to be used in file names.
+    pub fn declares_language(&self, language: &str) -> bool {
+        self.locales
+            .iter()
+            .flatten()
+            .chain(self.fallbacks.keys())
+            .any(|locale| locale.language() == language)
+    }
+
+    /// Locales to look up files of the given locale in, from most to least specific,
+    /// before falling back to unlocalized files.
+    ///
+    /// Unless configured otherwise, a locale falls back to the locales found by dropping
+    /// its most specific subtag, e.g. `zh.hant.tw` falls back to `zh.hant` and then `zh`.
+    pub fn locale_chain(&self, locale: &FileLocale) -> Vec<FileLocale> {
+        let mut chain = vec![locale.clone()];
+        match self.fallbacks.get(locale) {
+            Some(fallbacks) => chain.extend(fallbacks.iter().cloned()),
+            None => {
+                while let Some(parent) = chain.last().and_then(|l| l.parent()) {
+                    chain.push(parent);
+                }
+            }
+        }
+        chain
+    }
+
+    /// Dimensions files can vary in besides their locale, as declared by the site.
+    pub fn dimensions(&self) -> &[VariantDimension] {
+        &self.dimensions
+    }
+
+    /// Variants to look up files of the given variant in, from most to least specific,
+    /// ending with the default variant.
+    ///
+    /// Every dimension falls back on its own: the locale to the locales of its chain,
+    /// any other dimension to having no value. Earlier dimensions take precedence, e.g.
+    /// `nl.be.accessible` falls back to `nl.be`, `nl.accessible`, `nl`, `accessible` and
+    /// finally the default variant.
+    pub fn variant_chain(&self, variant: &FileVariant) -> Vec<FileVariant> {
+        let mut locales: Vec<Option<FileLocale>> = match variant.locale() {
+            Some(locale) => self.locale_chain(locale).into_iter().map(Some).collect(),
+            None => Vec::new(),
+        };
+        locales.push(None);
+        let mut combinations: Vec<Vec<(String, String)>> = vec![Vec::new()];
+        for value in variant.values() {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|values| {
+                    let mut with_value = values.clone();
+                    with_value.push(value.clone());
+                    [with_value, values]
+                })
+                .collect();
+        }
+        locales
+            .into_iter()
+            .flat_map(|locale| {
+                combinations
+                    .iter()
+                    .map(move |values| FileVariant::new(locale.clone(), values.clone()))
+            })
+            .collect()
+    }
+
+    /// Every variant to render pages in for the given locales: the cross product of
+    /// no locale and the locales with no value and the values of every dimension,
+    /// starting with the default variant.
+    pub fn variants(&self, locales: &[FileLocale]) -> Vec<FileVariant> {
+        let mut variants: Vec<FileVariant> = std::iter::once(None)
+            .chain(locales.iter().cloned().map(Some))
+            .map(FileVariant::from)
+            .collect();
+        for dimension in self.dimensions.iter() {
+            variants = variants
+                .into_iter()
+                .flat_map(|variant| {
+                    let values: Vec<_> = dimension
+                        .values
+                        .iter()
+                        .map(|value| {
+                            let mut values = variant.values().to_vec();
+                            values.push((dimension.name.clone(), value.clone()));
+                            FileVariant::new(variant.locale().cloned(), values)
+                        })
+                        .collect();
+                    std::iter::once(variant).chain(values)
+                })
+                .collect();
+        }
+        variants
+    }
+}
+
+fn parse_fallbacks(value: Value) -> Result<BTreeMap<FileLocale, Vec<FileLocale>>> {
+    let map = value
+        .to_mapping()
+        .ok_or_else(|| anyhow!("fallbacks must be a mapping of locales"))?;
+    let mut fallbacks = BTreeMap::new();
+    for (locale, value) in map {
+        let locales =
+            parse_locales(&value).with_context(|| format!("fallbacks of '{}'", locale))?;
+        fallbacks.insert(locale.parse()?, locales);
+    }
+    Ok(fallbacks)
+}
+
+fn parse_dimensions(value: Value) -> Result<Vec<VariantDimension>> {
+    lazy_static! {
+        static ref NAME: Regex = Regex::new(r"^[a-z][a-z\d_]*$").unwrap();
+        static ref VALUE: Regex = Regex::new(r"^[a-z\d][a-z\d\-]*$").unwrap();
+    }
+    let map = value
+        .to_mapping()
+        .ok_or_else(|| anyhow!("variants must be a mapping of dimensions to their values"))?;
+    let mut dimensions: Vec<VariantDimension> = Vec::new();
+    for (name, value) in map {
+        // the locale is the first dimension, and its name the page property of its value
+        if !NAME.is_match(&name) || name == "locale" || name == "url" {
+            return Err(anyhow!("invalid variant dimension '{}'", name));
+        }
+        let values = match value {
+            Value::Sequence(seq) => seq
+                .iter()
+                .map(|v| v.as_str().map(str::to_owned))
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("values of variant dimension '{}' must be a list", name))?;
+        for value in values.iter() {
+            if !VALUE.is_match(value) || value.parse::<FileLocale>().is_ok() {
+                return Err(anyhow!(
+                    "invalid value '{}' of variant dimension '{}', expected a lowercase name that isn't a locale",
+                    value,
+                    name
+                ));
+            }
+            if let Some(other) = dimensions.iter().find(|d| d.values.contains(value)) {
+                return Err(anyhow!(
+                    "value '{}' is declared by both variant dimensions '{}' and '{}'",
+                    value,
+                    other.name,
+                    name
+                ));
+            }
+        }
+        dimensions.push(VariantDimension { name, values });
+    }
+    Ok(dimensions)
+}
+
+fn parse_locales(value: &Value) -> Result<Vec<FileLocale>> {
+    match value {
+        Value::Null => Ok(Vec::new()),
+        Value::Sequence(seq) => seq
+            .iter()
+            .map(|v| match v.as_str() {
+                Some(locale) => Ok(locale.parse()?),
+                None => Err(anyhow!("must be a list of locales")),
+            })
+            .collect(),
+        _ => Err(anyhow!("must be a list of locales")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_chain() {
+        let config = Config::parse("fallbacks:\n  nl-BE: [fr.be, nl]\n  en.us: []\n").unwrap();
+        let test_cases = vec![
+            ("en.gb", vec!["en.gb", "en"]),
+            ("nl", vec!["nl"]),
+            ("nl.be", vec!["nl.be", "fr.be", "nl"]),
+            ("en.us", vec!["en.us"]),
+            ("zh-Hant-TW", vec!["zh.hant.tw", "zh.hant", "zh"]),
+        ];
+        for (locale, expected) in test_cases {
+            let chain = config.locale_chain(&locale.parse().unwrap());
+            let chain: Vec<_> = chain.iter().map(|l| l.as_str()).collect();
+            assert_eq!(chain, expected, "locale: {}", locale);
+        }
+
+        let config = Config::parse("locales: [en.gb, nl]").unwrap();
+        let locales: Vec<_> = config.locales().unwrap().iter().map(|l| l.as_str()).collect();
+        assert_eq!(locales, vec!["en.gb", "nl"]);
+
+        assert!(Config::parse("~").unwrap().locales().is_none());
+        assert!(Config::parse("locales: nl").is_err());
+        assert!(Config::parse("locales: [english]").is_err());
+        assert!(Config::parse("fallbacks: [en]").is_err());
+        assert!(Config::parse("fallback:\n  en.gb: [en]").is_err());
+    }
+
+    #[test]
+    fn test_variant_chain() {
+        let config = Config::parse("variants:\n  theme: [accessible, dark]\n  size: [print]\n").unwrap();
+        let dimensions: Vec<_> = config.dimensions().iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(dimensions, vec!["theme", "size"]);
+
+        let variant = FileVariant::parse("nl.be.accessible.print", &config).unwrap();
+        assert_eq!(variant.value("theme"), Some("accessible"));
+        let chain: Vec<_> = config.variant_chain(&variant).iter().map(|v| v.to_string()).collect();
+        let expected = vec![
+            "nl.be.accessible.print",
+            "nl.be.accessible",
+            "nl.be.print",
+            "nl.be",
+            "nl.accessible.print",
+            "nl.accessible",
+            "nl.print",
+            "nl",
+            "accessible.print",
+            "accessible",
+            "print",
+            "",
+        ];
+        assert_eq!(chain, expected);
+
+        let variants: Vec<_> = config
+            .variants(&["nl".parse().unwrap()])
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(variants.len(), 12);
+        assert_eq!(&variants[..4], &["", "print", "accessible", "accessible.print"]);
+        assert!(variants.contains(&"nl.dark.print".to_owned()));
+
+        assert!(Config::parse("variants:\n  locale: [accessible]").is_err());
+        assert!(Config::parse("variants:\n  theme: [nl]").is_err());
+        assert!(Config::parse("variants:\n  theme: [Dark]").is_err());
+        assert!(Config::parse("variants:\n  theme: [dark]\n  mode: [dark]").is_err());
+        assert!(Config::parse("variants: [theme]").is_err());
+    }
+}
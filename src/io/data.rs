@@ -1,9 +1,11 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::VecDeque;
+
+use indexmap::IndexMap;
 
 use serde_json;
-use serde_yaml;
 
 use super::path::{PathComponent, PathIter};
+use super::DateTime;
 
 pub fn first_value<'a, 'b, I>(path_it: I, values: &'a [Value]) -> Option<&'a Value>
     where I: Into<PathIter<'b>>
@@ -22,9 +24,12 @@ pub enum Value {
     Null,
     String(String),
     Boolean(bool),
-    Number(f64),
+    Integer(i64),
+    Float(f64),
+    DateTime(DateTime),
     Sequence(Vec<Value>),
-    Mapping(HashMap<String, Value>),
+    // keeps the order of the keys as defined by the author
+    Mapping(IndexMap<String, Value>),
 }
 
 impl Value {
@@ -63,6 +68,24 @@ impl Value {
         }
     }
 
+    /// Canonical text of a primitive value, as used when it is included,
+    /// sequences and mappings have none.
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            Value::Null => Some(String::new()),
+            Value::String(s) => Some(s.clone()),
+            Value::Boolean(b) => Some(b.to_string()),
+            Value::Integer(x) => Some(x.to_string()),
+            // keep floats recognizable as such, e.g. `2.0` rather than `2`
+            Value::Float(x) if x.is_finite() && x.fract() == 0.0 && x.abs() < 1e16 => {
+                Some(format!("{:.1}", x))
+            }
+            Value::Float(x) => Some(x.to_string()),
+            Value::DateTime(d) => Some(d.to_string()),
+            Value::Sequence(_) | Value::Mapping(_) => None,
+        }
+    }
+
     pub fn as_boolean(&self) -> Option<bool> {
         match self {
             Value::Boolean(b) => Some(*b),
@@ -70,9 +93,23 @@ impl Value {
         }
     }
 
-    pub fn as_number(&self) -> Option<f64> {
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
         match self {
-            Value::Number(x) => Some(*x),
+            Value::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn as_datetime(&self) -> Option<&DateTime> {
+        match self {
+            Value::DateTime(d) => Some(d),
             _ => None,
         }
     }
@@ -84,19 +121,37 @@ impl Value {
         }
     }
 
-    pub fn as_mapping(&self) -> Option<&HashMap<String, Value>> {
+    pub fn as_mapping(&self) -> Option<&IndexMap<String, Value>> {
         match self {
-            Value::Mapping(m) => Some(&m),
+            Value::Mapping(m) => Some(m),
             _ => None,
         }
     }
 
-    pub fn to_mapping(self) -> Option<HashMap<String, Value>> {
+    pub fn to_mapping(self) -> Option<IndexMap<String, Value>> {
         match self {
             Value::Mapping(m) => Some(m),
             _ => None,
         }
     }
+
+    /// Override this value with the given value, where mappings are merged key by key,
+    /// such that keys missing in the given mapping keep their value.
+    pub fn merge(&mut self, other: Value) {
+        match (self, other) {
+            (Value::Mapping(map), Value::Mapping(other)) => {
+                for (key, value) in other {
+                    match map.get_mut(&key) {
+                        Some(existing) => existing.merge(value),
+                        None => {
+                            map.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (this, other) => *this = other,
+        }
+    }
 }
 
 impl From<()> for Value {
@@ -125,79 +180,83 @@ impl From<bool> for Value {
 
 impl From<f32> for Value {
     fn from(x: f32) -> Value {
-        Value::Number(x as f64)
+        Value::Float(x as f64)
     }
 }
 
 impl From<f64> for Value {
     fn from(x: f64) -> Value {
-        Value::Number(x)
+        Value::Float(x)
     }
 }
 
 impl From<i8> for Value {
     fn from(x: i8) -> Value {
-        Value::Number(x as f64)
+        Value::Integer(x as i64)
     }
 }
 
 impl From<i16> for Value {
     fn from(x: i16) -> Value {
-        Value::Number(x as f64)
+        Value::Integer(x as i64)
     }
 }
 
 impl From<i32> for Value {
     fn from(x: i32) -> Value {
-        Value::Number(x as f64)
+        Value::Integer(x as i64)
     }
 }
 
 impl From<i64> for Value {
     fn from(x: i64) -> Value {
-        Value::Number(x as f64)
+        Value::Integer(x)
     }
 }
 
 impl From<i128> for Value {
     fn from(x: i128) -> Value {
-        Value::Number(x as f64)
+        // integers out of range lose precision rather than being cut off
+        i64::try_from(x).map(Value::Integer).unwrap_or(Value::Float(x as f64))
     }
 }
 
 impl From<usize> for Value {
     fn from(x: usize) -> Value {
-        Value::Number(x as f64)
+        // integers out of range lose precision rather than being cut off
+        i64::try_from(x).map(Value::Integer).unwrap_or(Value::Float(x as f64))
     }
 }
 
 impl From<u8> for Value {
     fn from(x: u8) -> Value {
-        Value::Number(x as f64)
+        Value::Integer(x as i64)
     }
 }
 
 impl From<u16> for Value {
     fn from(x: u16) -> Value {
-        Value::Number(x as f64)
+        Value::Integer(x as i64)
     }
 }
 
 impl From<u32> for Value {
     fn from(x: u32) -> Value {
-        Value::Number(x as f64)
+        Value::Integer(x as i64)
     }
 }
 
 impl From<u64> for Value {
     fn from(x: u64) -> Value {
-        Value::Number(x as f64)
+        // integers out of range lose precision rather than being cut off
+        i64::try_from(x).map(Value::Integer).unwrap_or(Value::Float(x as f64))
     }
 }
 
 impl From<u128> for Value {
     fn from(x: u128) -> Value {
-        Value::Number(x as f64)
+        // integers out of range lose precision rather than being cut off
+        i64::try_from(x).map(Value::Integer).unwrap_or(Value::Float(x as f64))
     }
 }
 
@@ -221,60 +280,40 @@ where
     }
 }
 
-impl<T> From<HashMap<String, T>> for Value
+impl<T> From<IndexMap<String, T>> for Value
 where
     T: Into<Value>,
 {
-    fn from(m: HashMap<String, T>) -> Value {
-        let m: HashMap<String, Value> = m.into_iter().map(|(k, v)| (k, v.into())).collect();
+    fn from(m: IndexMap<String, T>) -> Value {
+        let m: IndexMap<String, Value> = m.into_iter().map(|(k, v)| (k, v.into())).collect();
         Value::Mapping(m)
     }
 }
 
-impl From<serde_yaml::Value> for Value {
-    fn from(v: serde_yaml::Value) -> Value {
-        match v {
-            serde_yaml::Value::Null => Value::Null,
-            serde_yaml::Value::String(s) => Value::String(s),
-            serde_yaml::Value::Bool(b) => Value::Boolean(b),
-            serde_yaml::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(std::f64::NAN)),
-            serde_yaml::Value::Sequence(s) => s.into(),
-            serde_yaml::Value::Mapping(m) => {
-                let m: HashMap<String, Value> = m.into_iter()
-                    .filter(|(k, _)| match k {
-                        // filter out complex keys as these can anyhow not be indexed nicely by TSG user
-                        serde_yaml::Value::Sequence(_) | serde_yaml::Value::Mapping(_) => false,
-                        _ => true,
-                    })
-                    .map(|(k, v)| (match k {
-                        serde_yaml::Value::String(s) => s,
-                        serde_yaml::Value::Null => "".to_owned(),
-                        serde_yaml::Value::Number(n) => n.to_string(),
-                        serde_yaml::Value::Bool(b) => b.to_string(),
-                        _ => panic!("report a bug if this line is ever reached, should be impossible due to filter"),
-                    }, v.into())).collect();
-                Value::Mapping(m)
-            }
-        }
-    }
-}
-
 impl From<serde_json::Value> for Value {
     fn from(v: serde_json::Value) -> Value {
         match v {
             serde_json::Value::Null => Value::Null,
             serde_json::Value::String(s) => Value::String(s),
             serde_json::Value::Bool(b) => Value::Boolean(b),
-            serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(std::f64::NAN)),
+            serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(x), _) => Value::Integer(x),
+                (None, Some(x)) => x.into(),
+                _ => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
             serde_json::Value::Array(arr) => arr.into(),
             serde_json::Value::Object(o) => {
-                let m: HashMap<String, Value> = o.into_iter().map(|(k, v)| (k, v.into())).collect();
+                let m: IndexMap<String, Value> = o.into_iter().map(|(k, v)| (k, v.into())).collect();
                 Value::Mapping(m)
             }
         }
     }
 }
 
+/// Iterator over all values found at a path, which can contain wildcards.
+///
+/// Values are found breadth first, where the values of a mapping and the items of a sequence
+/// are found in the order in which they are defined.
 pub struct ValueIter<'a, 'b> {
     stack: VecDeque<ValueIterInner<'a, 'b>>,
 }
@@ -284,6 +323,8 @@ struct ValueIterInner<'a, 'b> {
     path: Vec<PathComponent<'b>>,
     path_index: usize,
     recursive: bool,
+    // keys and indices leading from the value iterated to the current root
+    trail: Vec<String>,
 }
 
 impl<'a, 'b> ValueIter<'a, 'b> {
@@ -296,19 +337,17 @@ impl<'a, 'b> ValueIter<'a, 'b> {
         stack.push_front(root_value_iter);
         ValueIter { stack }
     }
-}
-
-impl<'a, 'b> Iterator for ValueIter<'a, 'b> {
-    type Item = &'a Value;
 
-    fn next(&mut self) -> Option<&'a Value> {
+    /// Next value found, along with its resolved path,
+    /// e.g. `posts.0.title` when looking up `posts.*.title`.
+    pub fn next_with_path(&mut self) -> Option<(String, &'a Value)> {
         let mut inner_stack = VecDeque::new();
         loop {
             if self.stack.is_empty() {
                 return None;
             }
             let result = self.stack[0].next_value(&mut inner_stack);
-            if inner_stack.len() > 0 {
+            if !inner_stack.is_empty() {
                 self.stack.append(&mut inner_stack);
             }
             match result {
@@ -316,12 +355,20 @@ impl<'a, 'b> Iterator for ValueIter<'a, 'b> {
                     self.stack.pop_front();
                     continue;
                 }
-                Some(value) => return Some(value),
+                Some(value) => return Some((self.stack[0].trail.join("."), value)),
             }
         }
     }
 }
 
+impl<'a, 'b> Iterator for ValueIter<'a, 'b> {
+    type Item = &'a Value;
+
+    fn next(&mut self) -> Option<&'a Value> {
+        self.next_with_path().map(|(_, value)| value)
+    }
+}
+
 impl<'a, 'b> ValueIterInner<'a, 'b> {
     pub fn new<T>(value: &'a Value, t: T) -> ValueIterInner<'a, 'b>
     where
@@ -332,6 +379,25 @@ impl<'a, 'b> ValueIterInner<'a, 'b> {
             path: t.into().collect(),
             path_index: 0,
             recursive: false,
+            trail: Vec::new(),
+        }
+    }
+
+    fn child(
+        &self,
+        key: String,
+        root: &'a Value,
+        path: Vec<PathComponent<'b>>,
+        recursive: bool,
+    ) -> ValueIterInner<'a, 'b> {
+        let mut trail = self.trail.clone();
+        trail.push(key);
+        ValueIterInner {
+            root,
+            path,
+            path_index: 0,
+            recursive,
+            trail,
         }
     }
 
@@ -340,7 +406,12 @@ impl<'a, 'b> ValueIterInner<'a, 'b> {
             match self.path[self.path_index] {
                 PathComponent::Name(name) => {
                     let opt_value = match self.root {
-                        Value::Null | Value::String(_) | Value::Boolean(_) | Value::Number(_) => {
+                        Value::Null
+                    | Value::String(_)
+                    | Value::Boolean(_)
+                    | Value::Integer(_)
+                    | Value::Float(_)
+                    | Value::DateTime(_) => {
                             None
                         }
                         Value::Sequence(seq) => name
@@ -355,24 +426,24 @@ impl<'a, 'b> ValueIterInner<'a, 'b> {
                                         if index == value_index {
                                             continue;
                                         }
-                                        stack.push_back(ValueIterInner {
-                                            root: value,
-                                            path: self.path[self.path_index..].to_vec(),
-                                            path_index: 0,
-                                            recursive: true,
-                                        });
+                                        stack.push_back(self.child(
+                                            value_index.to_string(),
+                                            value,
+                                            self.path[self.path_index..].to_vec(),
+                                            true,
+                                        ));
                                     }
                                 }
-                                Some(&seq[index])
+                                Some((index.to_string(), &seq[index]))
                             })
                             .or_else(|| {
-                                for value in seq {
-                                    stack.push_back(ValueIterInner {
-                                        root: value,
-                                        path: self.path[self.path_index..].to_vec(),
-                                        path_index: 0,
-                                        recursive: true,
-                                    });
+                                for (index, value) in seq.iter().enumerate() {
+                                    stack.push_back(self.child(
+                                        index.to_string(),
+                                        value,
+                                        self.path[self.path_index..].to_vec(),
+                                        true,
+                                    ));
                                 }
                                 None
                             }),
@@ -384,22 +455,23 @@ impl<'a, 'b> ValueIterInner<'a, 'b> {
                                     if result.is_some() && key == &name {
                                         continue;
                                     }
-                                    stack.push_back(ValueIterInner {
-                                        root: value,
-                                        path: self.path[self.path_index..].to_vec(),
-                                        path_index: 0,
-                                        recursive: true,
-                                    });
+                                    stack.push_back(self.child(
+                                        key.clone(),
+                                        value,
+                                        self.path[self.path_index..].to_vec(),
+                                        true,
+                                    ));
                                 }
                             }
-                            result
+                            result.map(|value| (name, value))
                         }
                     };
                     match opt_value {
-                        Some(value) => {
+                        Some((key, value)) => {
                             self.path_index += 1;
                             self.root = value;
                             self.recursive = false;
+                            self.trail.push(key);
                             continue;
                         }
                         None => return None,
@@ -408,31 +480,36 @@ impl<'a, 'b> ValueIterInner<'a, 'b> {
                 // no need to take into account recursive-ness when at an "any" path,
                 // as this is not possible due to the normalization process applied on a map prior to using it in ValueIter
                 PathComponent::Any => match self.root {
-                    Value::Null | Value::String(_) | Value::Boolean(_) | Value::Number(_) => {
+                    Value::Null
+                    | Value::String(_)
+                    | Value::Boolean(_)
+                    | Value::Integer(_)
+                    | Value::Float(_)
+                    | Value::DateTime(_) => {
                         // return value if last element, otherwise will end up being None
                         self.path_index += 1;
                         continue;
                     }
                     Value::Sequence(seq) => {
-                        for value in seq {
-                            stack.push_back(ValueIterInner {
-                                root: value,
-                                path: self.path[self.path_index + 1..].to_vec(),
-                                path_index: 0,
-                                recursive: false,
-                            });
+                        for (index, value) in seq.iter().enumerate() {
+                            stack.push_back(self.child(
+                                index.to_string(),
+                                value,
+                                self.path[self.path_index + 1..].to_vec(),
+                                false,
+                            ));
                         }
                         self.path_index = self.path.len();
                         return None;
                     }
                     Value::Mapping(map) => {
-                        for value in map.values() {
-                            stack.push_back(ValueIterInner {
-                                root: value,
-                                path: self.path[self.path_index + 1..].to_vec(),
-                                path_index: 0,
-                                recursive: false,
-                            });
+                        for (key, value) in map {
+                            stack.push_back(self.child(
+                                key.clone(),
+                                value,
+                                self.path[self.path_index + 1..].to_vec(),
+                                false,
+                            ));
                         }
                         self.path_index = self.path.len();
                         return None;
@@ -441,32 +518,37 @@ impl<'a, 'b> ValueIterInner<'a, 'b> {
                 // no need to take into account recursive-ness when at an "anyRecursive" path,
                 // as this is not possible due to the normalization process applied on a map prior to using it in ValueIter
                 PathComponent::AnyRecursive => match self.root {
-                    Value::Null | Value::String(_) | Value::Boolean(_) | Value::Number(_) => {
+                    Value::Null
+                    | Value::String(_)
+                    | Value::Boolean(_)
+                    | Value::Integer(_)
+                    | Value::Float(_)
+                    | Value::DateTime(_) => {
                         // return value if last element, otherwise will end up being None
                         self.path_index += 1;
                         self.recursive = true;
                         continue;
                     }
                     Value::Sequence(seq) => {
-                        for value in seq {
-                            stack.push_back(ValueIterInner {
-                                root: value,
-                                path: self.path[self.path_index + 1..].to_vec(),
-                                path_index: 0,
-                                recursive: true,
-                            });
+                        for (index, value) in seq.iter().enumerate() {
+                            stack.push_back(self.child(
+                                index.to_string(),
+                                value,
+                                self.path[self.path_index + 1..].to_vec(),
+                                true,
+                            ));
                         }
                         self.path_index = self.path.len();
                         return None;
                     }
                     Value::Mapping(map) => {
-                        for value in map.values() {
-                            stack.push_back(ValueIterInner {
-                                root: value,
-                                path: self.path[self.path_index + 1..].to_vec(),
-                                path_index: 0,
-                                recursive: true,
-                            });
+                        for (key, value) in map {
+                            stack.push_back(self.child(
+                                key.clone(),
+                                value,
+                                self.path[self.path_index + 1..].to_vec(),
+                                true,
+                            ));
                         }
                         self.path_index = self.path.len();
                         return None;
@@ -475,6 +557,29 @@ impl<'a, 'b> ValueIterInner<'a, 'b> {
             }
         }
 
+        if self.path_index == self.path.len() {
+            // path is fully consumed, yield the current root (only once),
+            // and when recursive also continue into all its children
+            self.path_index += 1;
+            if self.recursive {
+                let children: Vec<(String, &'a Value)> = match self.root {
+                    Value::Sequence(seq) => seq
+                        .iter()
+                        .enumerate()
+                        .map(|(index, value)| (index.to_string(), value))
+                        .collect(),
+                    Value::Mapping(map) => {
+                        map.iter().map(|(key, value)| (key.clone(), value)).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                for (key, value) in children {
+                    stack.push_back(self.child(key, value, Vec::new(), true));
+                }
+            }
+            return Some(self.root);
+        }
+
         None
     }
 }
@@ -0,0 +1,101 @@
+use std::fmt;
+
+use chrono::{FixedOffset, NaiveDate, NaiveTime, SecondsFormat, TimeZone};
+use regex::Regex;
+
+/// A date, optionally with a time, as written in YAML,
+/// e.g. `2021-02-01` or `2021-02-01T10:30:00+02:00`.
+///
+/// A time without an offset is in UTC, as is the case for YAML timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    datetime: chrono::DateTime<FixedOffset>,
+    has_time: bool,
+}
+
+impl DateTime {
+    /// Parse a YAML timestamp, returning `None` for any other string.
+    pub fn parse(s: &str) -> Option<DateTime> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(
+                r"^(?P<date>\d{4}-\d{1,2}-\d{1,2})(?:(?:[Tt]|[ \t]+)(?P<time>\d{1,2}:\d{2}:\d{2}(?:\.\d+)?)[ \t]*(?P<offset>Z|[+-]\d{1,2}(?::?\d{2})?)?)?$"
+            )
+            .unwrap();
+        }
+        let captures = RE.captures(s.trim())?;
+        let date = NaiveDate::parse_from_str(&captures["date"], "%Y-%m-%d").ok()?;
+        let time = match captures.name("time") {
+            None => NaiveTime::MIN,
+            Some(time) => NaiveTime::parse_from_str(time.as_str(), "%H:%M:%S%.f").ok()?,
+        };
+        let offset = match captures.name("offset").map(|m| m.as_str()) {
+            None | Some("Z") => 0,
+            Some(offset) => {
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+                let digits: String = offset[1..].chars().filter(|c| *c != ':').collect();
+                let (hours, minutes) = if digits.len() > 2 {
+                    digits.split_at(digits.len() - 2)
+                } else {
+                    (digits.as_str(), "0")
+                };
+                sign * (hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60)
+            }
+        };
+        let datetime = FixedOffset::east_opt(offset)?
+            .from_local_datetime(&date.and_time(time))
+            .single()?;
+        Some(DateTime {
+            datetime,
+            has_time: captures.name("time").is_some(),
+        })
+    }
+
+    /// Seconds since the Unix epoch, e.g. to sort dates chronologically.
+    pub fn timestamp(&self) -> i64 {
+        self.datetime.timestamp()
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Render as `2021-02-01` for a date,
+    /// or in RFC 3339 format, e.g. `2021-02-01T10:30:00+02:00`, for a date with a time.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.has_time {
+            write!(
+                f,
+                "{}",
+                self.datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+            )
+        } else {
+            write!(f, "{}", self.datetime.format("%Y-%m-%d"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let test_cases = vec![
+            ("2021-02-01", Some("2021-02-01")),
+            ("2021-2-1", Some("2021-02-01")),
+            ("2001-12-14t21:59:43.10-05:00", Some("2001-12-14T21:59:43.100-05:00")),
+            ("2001-12-14 21:59:43.10 -5", Some("2001-12-14T21:59:43.100-05:00")),
+            ("2001-12-15T02:59:43Z", Some("2001-12-15T02:59:43Z")),
+            ("2001-12-15 2:59:43", Some("2001-12-15T02:59:43Z")),
+            ("2021-02-30", None),
+            ("2021-02-01 at noon", None),
+            ("hello", None),
+        ];
+        for (input, expected) in test_cases {
+            let output = DateTime::parse(input).map(|d| d.to_string());
+            assert_eq!(output.as_deref(), expected, "input: {}", input);
+        }
+
+        let a = DateTime::parse("2001-12-14 21:59:43 -5").unwrap();
+        let b = DateTime::parse("2001-12-15T02:59:43Z").unwrap();
+        assert_eq!(a.timestamp(), b.timestamp());
+    }
+}
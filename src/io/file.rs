@@ -4,11 +4,12 @@ use std::error::Error;
 use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use anyhow::{anyhow, Result};
 use regex::Regex;
 
-use super::Meta;
+use super::{Config, Meta};
 
 #[derive(Debug, Copy, Clone)]
 pub enum FileKind {
@@ -17,8 +18,10 @@ pub enum FileKind {
     Page,
 }
 
-impl FileKind {
-    pub fn from_str(s: &str) -> Result<FileKind> {
+impl FromStr for FileKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<FileKind> {
         Ok(match s.to_lowercase().as_str() {
             "includes" => FileKind::Include,
             "layouts" => FileKind::Layout,
@@ -38,8 +41,10 @@ pub enum FileFormat {
     Bash,
 }
 
-impl FileFormat {
-    pub fn from_str(s: &str) -> std::result::Result<FileFormat, FileInfoError> {
+impl FromStr for FileFormat {
+    type Err = FileInfoError;
+
+    fn from_str(s: &str) -> std::result::Result<FileFormat, FileInfoError> {
         Ok(match s.to_lowercase().as_str() {
             "html" | "htm" | "xhtml" | "xml" => FileFormat::Html,
             "yaml" | "yml" => FileFormat::Yaml,
@@ -54,37 +59,271 @@ impl FileFormat {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Two-letter ISO 639-1 language codes, in alphabetical order.
+const ISO_639_1: &[&str] = &[
+    "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az", "ba", "be", "bg", "bh",
+    "bi", "bm", "bn", "bo", "br", "bs", "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy", "da",
+    "de", "dv", "dz", "ee", "el", "en", "eo", "es", "et", "eu", "fa", "ff", "fi", "fj", "fo", "fr",
+    "fy", "ga", "gd", "gl", "gn", "gu", "gv", "ha", "he", "hi", "ho", "hr", "ht", "hu", "hy", "hz",
+    "ia", "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu", "ja", "jv", "ka", "kg", "ki", "kj",
+    "kk", "kl", "km", "kn", "ko", "kr", "ks", "ku", "kv", "kw", "ky", "la", "lb", "lg", "li", "ln",
+    "lo", "lt", "lu", "lv", "mg", "mh", "mi", "mk", "ml", "mn", "mr", "ms", "mt", "my", "na", "nb",
+    "nd", "ne", "ng", "nl", "nn", "no", "nr", "nv", "ny", "oc", "oj", "om", "or", "os", "pa", "pi",
+    "pl", "ps", "pt", "qu", "rm", "rn", "ro", "ru", "rw", "sa", "sc", "sd", "se", "sg", "si", "sk",
+    "sl", "sm", "sn", "so", "sq", "sr", "ss", "st", "su", "sv", "sw", "ta", "te", "tg", "th", "ti",
+    "tk", "tl", "tn", "to", "tr", "ts", "tt", "tw", "ty", "ug", "uk", "ur", "uz", "ve", "vi", "vo",
+    "wa", "wo", "xh", "yi", "yo", "za", "zh", "zu",
+];
+
+/// A BCP 47 language tag made of a language, optionally followed by a script and region,
+/// e.g. `en`, `en.gb` or `zh.hant.tw`.
+///
+/// Subtags can be separated by dots, dashes or underscores and are matched case-insensitively,
+/// such that `en-GB` and `en.gb` are the same locale. Two-letter languages have to be
+/// ISO 639-1 codes, while three-letter languages are only known once declared by the site,
+/// as these are easily confused with words such as `new` or `old`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FileLocale {
-    raw_str: String,
+    language: String,
+    script: Option<String>,
+    region: Option<String>,
+    // canonical form, lowercase subtags separated by dots
+    tag: String,
 }
 
 impl FileLocale {
-    pub fn from_str(s: &str) -> FileLocale {
+    /// Canonical form of the locale, e.g. `en.gb`, as used for output paths.
+    pub fn as_str(&self) -> &str {
+        &self.tag
+    }
+
+    /// Language subtag in lowercase, e.g. `en`.
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Script subtag in lowercase, e.g. `hant`.
+    pub fn script(&self) -> Option<&str> {
+        self.script.as_deref()
+    }
+
+    /// Region subtag in lowercase, e.g. `gb` or `419`.
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    /// The locale without its most specific subtag, e.g. `en` for `en.gb`,
+    /// or `None` for a locale that is only a language.
+    pub fn parent(&self) -> Option<FileLocale> {
+        let (script, region) = match (&self.script, &self.region) {
+            (None, None) => return None,
+            (script, Some(_)) => (script.clone(), None),
+            (Some(_), None) => (None, None),
+        };
+        Some(FileLocale::new(self.language.clone(), script, region))
+    }
+
+    fn new(language: String, script: Option<String>, region: Option<String>) -> FileLocale {
+        let tag = std::iter::once(language.as_str())
+            .chain(script.as_deref())
+            .chain(region.as_deref())
+            .collect::<Vec<_>>()
+            .join(".");
         FileLocale {
-            raw_str: String::from(s),
+            language,
+            script,
+            region,
+            tag,
+        }
+    }
+}
+
+impl FromStr for FileLocale {
+    type Err = FileInfoError;
+
+    fn from_str(s: &str) -> std::result::Result<FileLocale, FileInfoError> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"(?i)^(?P<language>[a-z]{2,3})([._-](?P<script>[a-z]{4}))?([._-](?P<region>[a-z]{2}|\d{3}))?$").unwrap();
+        }
+        let m = RE
+            .captures(s.trim_start_matches('.'))
+            .ok_or_else(|| FileInfoError::UnexpectedLocale(String::from(s)))?;
+        let subtag = |name| m.name(name).map(|m| m.as_str().to_lowercase());
+        let language = subtag("language").unwrap();
+        if language.len() == 2 && ISO_639_1.binary_search(&language.as_str()).is_err() {
+            return Err(FileInfoError::UnexpectedLocale(String::from(s)));
+        }
+        Ok(FileLocale::new(
+            language,
+            subtag("script"),
+            subtag("region"),
+        ))
+    }
+}
+
+impl fmt::Display for FileLocale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.tag)
+    }
+}
+
+/// A dimension in which files can vary besides their locale, such as `theme`,
+/// along with the values files can have for it, such as `accessible`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantDimension {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+/// The variant of a file: its locale, followed by its value for each of the other dimensions,
+/// e.g. `nl` and `accessible` for `index.nl.accessible.md`.
+///
+/// The default variant, without a locale or any other value, is the one of unsuffixed files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileVariant {
+    locale: Option<FileLocale>,
+    // pairs of dimension and value, in the order the dimensions are declared
+    values: Vec<(String, String)>,
+}
+
+impl FileVariant {
+    pub fn new(locale: Option<FileLocale>, values: Vec<(String, String)>) -> FileVariant {
+        FileVariant { locale, values }
+    }
+
+    /// Parse the suffix of a file name, such as `nl.accessible`, where the values of
+    /// the dimensions of the given configuration follow the locale in the order of the dimensions.
+    ///
+    /// A locale of which the language isn't a two-letter code has to be declared by the configuration.
+    pub fn parse(s: &str, config: &Config) -> std::result::Result<FileVariant, FileInfoError> {
+        let dimensions = config.dimensions();
+        let s = s.trim_start_matches('.');
+        let mut rest = s;
+        let mut values = Vec::new();
+        for dimension in dimensions.iter().rev() {
+            let (head, last) = rest.rsplit_once('.').unwrap_or(("", rest));
+            if let Some(value) = dimension.values.iter().find(|v| v.eq_ignore_ascii_case(last)) {
+                values.push((dimension.name.clone(), value.clone()));
+                rest = head;
+            }
+        }
+        values.reverse();
+        let locale: Option<FileLocale> = match rest {
+            "" => None,
+            rest if dimensions.is_empty() => Some(rest.parse()?),
+            rest => Some(
+                rest.parse()
+                    .map_err(|_| FileInfoError::UnexpectedVariant(String::from(s)))?,
+            ),
+        };
+        if let Some(locale) = &locale {
+            if locale.language().len() != 2 && !config.declares_language(locale.language()) {
+                return Err(FileInfoError::UndeclaredLocale(locale.to_string()));
+            }
+        }
+        Ok(FileVariant { locale, values })
+    }
+
+    pub fn locale(&self) -> Option<&FileLocale> {
+        self.locale.as_ref()
+    }
+
+    /// Value of the variant for the given dimension, if any.
+    pub fn value(&self, dimension: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(name, _)| name == dimension)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Pairs of dimension and value, in the order the dimensions are declared.
+    pub fn values(&self) -> &[(String, String)] {
+        &self.values
+    }
+
+    /// Whether this is the default variant, without a locale or any other value.
+    pub fn is_default(&self) -> bool {
+        self.locale.is_none() && self.values.is_empty()
+    }
+
+    /// The locale and values of the variant, most significant first, e.g. `["en.gb", "accessible"]`.
+    pub fn parts(&self) -> Vec<&str> {
+        self.locale
+            .iter()
+            .map(|locale| locale.as_str())
+            .chain(self.values.iter().map(|(_, value)| value.as_str()))
+            .collect()
+    }
+}
+
+impl From<Option<FileLocale>> for FileVariant {
+    fn from(locale: Option<FileLocale>) -> FileVariant {
+        FileVariant {
+            locale,
+            values: Vec::new(),
         }
     }
 }
 
+impl fmt::Display for FileVariant {
+    /// Render as the suffix of a file name, e.g. `nl.accessible`, empty for the default variant.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.parts().join("."))
+    }
+}
+
 #[derive(Clone)]
 pub struct FileInfo {
     kind: FileKind,
     path: String,
     directory: Option<Range<usize>>,
     name: Range<usize>,
-    locale: Option<FileLocale>,
+    variant: FileVariant,
     format: FileFormat,
 }
 
 impl FileInfo {
+    /// Parse the path of a file of which the name can only be suffixed by a locale.
     pub fn new(raw_path: &str) -> std::result::Result<FileInfo, FileInfoError> {
+        FileInfo::with_config(raw_path, &Config::default())
+    }
+
+    /// Parse the path of a file of which the name can be suffixed by a locale,
+    /// followed by values of the variant dimensions of the given configuration.
+    ///
+    /// The path has to start with the directory of its kind, e.g. `pages/index.md`.
+    pub fn with_config(raw_path: &str, config: &Config) -> std::result::Result<FileInfo, FileInfoError> {
+        FileInfo::parse(raw_path, 0, config)
+    }
+
+    /// Parse the path of a file within the workspace at the given root,
+    /// such that its kind and directory only follow from its path within the workspace.
+    pub fn in_workspace(
+        root: &Path,
+        path: &Path,
+        config: &Config,
+    ) -> std::result::Result<FileInfo, FileInfoError> {
+        let raw_path = path.to_str().ok_or(FileInfoError::InvalidPath)?;
+        let relative = path
+            .strip_prefix(root)
+            .ok()
+            .and_then(Path::to_str)
+            .ok_or_else(|| FileInfoError::UnexpectedFilePath(String::from(raw_path)))?;
+        FileInfo::parse(raw_path, raw_path.len() - relative.len(), config)
+    }
+
+    /// Parse the path, of which the part within the workspace starts at the given byte offset.
+    fn parse(
+        raw_path: &str,
+        start: usize,
+        config: &Config,
+    ) -> std::result::Result<FileInfo, FileInfoError> {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"(?i)(?P<kind>includes|layouts|pages)(?P<dir>((/|\\)[^/\\]+)+)?(/|\\)(?P<name>\s+)(?P<locale>(\.[a-z\-_\d]+)+)?(\.(?P<ext>[a-z]+)$").unwrap();
+            static ref RE: Regex = Regex::new(r"(?i)^(?P<kind>includes|layouts|pages)(?P<dir>((/|\\)[^/\\]+)+)?(/|\\)(?P<name>[^/\\.]+)(?P<locale>(\.[a-z\-_\d]+)+)?\.(?P<ext>[a-z]+)$").unwrap();
         }
         // extract raw name, locale (opt) and extension (indicates file format)
         let (raw_kind, raw_dir, raw_name, raw_locale_opt, raw_ext, path) =
-            match RE.captures(raw_path) {
+            match RE.captures(&raw_path[start..]) {
                 Some(m) => (
                     m.name("kind").unwrap(),
                     m.name("dir"), // dir is optional, and not defined if direct in root of kind
@@ -96,22 +335,25 @@ impl FileInfo {
                 None => return Err(FileInfoError::UnexpectedFilePath(String::from(raw_path))),
             };
         // "parse" the file format from the file extension
-        let file_format = FileFormat::from_str(raw_ext.as_str())?;
-        // optionally "parse" the locale from the locale part
-        let locale = raw_locale_opt.and_then(|m| Some(FileLocale::from_str(m.as_str())));
+        let file_format = raw_ext.as_str().parse()?;
+        // optionally parse the variant from the locale part
+        let variant = match raw_locale_opt {
+            Some(m) => FileVariant::parse(m.as_str(), config)?,
+            None => FileVariant::default(),
+        };
         // "parse" the kind dir from file path, no need to do fancy here as the
         // regex above should have ensured it is one of our expected kinds
-        let kind = FileKind::from_str(raw_kind.as_str()).unwrap();
+        let kind = raw_kind.as_str().parse().unwrap();
         // optionally turn the dir into a String
-        let directory = raw_dir.and_then(|dir| Some(dir.range()));
+        let directory = raw_dir.map(|dir| dir.start() + start..dir.end() + start);
 
         // return the parsed File Info
         Ok(FileInfo {
-            kind: kind,
-            path: path,
-            directory: directory,
-            name: raw_name.range(),
-            locale: locale,
+            kind,
+            path,
+            directory,
+            name: raw_name.start() + start..raw_name.end() + start,
+            variant,
             format: file_format,
         })
     }
@@ -127,7 +369,7 @@ impl FileInfo {
     pub fn directory(&self) -> Option<&str> {
         self.directory
             .as_ref()
-            .and_then(|range| Some(&self.path[range.start..range.end]))
+            .map(|range| &self.path[range.start..range.end])
     }
 
     pub fn name(&self) -> &str {
@@ -135,7 +377,11 @@ impl FileInfo {
     }
 
     pub fn locale(&self) -> Option<&FileLocale> {
-        self.locale.as_ref()
+        self.variant.locale()
+    }
+
+    pub fn variant(&self) -> &FileVariant {
+        &self.variant
     }
 
     pub fn format(&self) -> FileFormat {
@@ -170,6 +416,9 @@ pub enum FileInfoError {
     UnexpectedFileFormat(String),
     InvalidPath,
     UnexpectedFilePath(String),
+    UnexpectedLocale(String),
+    UnexpectedVariant(String),
+    UndeclaredLocale(String),
 }
 
 impl Error for FileInfoError {}
@@ -182,10 +431,26 @@ impl fmt::Display for FileInfoError {
             }
             FileInfoError::InvalidPath => write!(f, "invalid file path"),
             FileInfoError::UnexpectedFilePath(path) => write!(f, "unexpected file path: {}", path),
+            FileInfoError::UnexpectedLocale(locale) => write!(
+                f,
+                "unexpected locale: {}, expected a language optionally followed by a script and region, such as en.gb",
+                locale.trim_start_matches('.')
+            ),
+            FileInfoError::UnexpectedVariant(variant) => write!(
+                f,
+                "unexpected variant: {}, expected a locale and/or values of the declared variant dimensions, such as en.gb.accessible",
+                variant
+            ),
+            FileInfoError::UndeclaredLocale(locale) => write!(
+                f,
+                "undeclared locale: {}, locales of which the language isn't a two-letter ISO 639-1 code have to be declared in the locales or fallbacks of tsg.yml",
+                locale
+            ),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct File {
     file_info: FileInfo,
     meta: Option<Meta>,
@@ -193,9 +458,9 @@ pub struct File {
 }
 
 impl File {
-    pub fn read<P: AsRef<Path>>(path: P) -> Result<File> {
-        let path = path.as_ref();
-        let file_info: FileInfo = path.try_into()?;
+    /// Read the file at the given path within the workspace at the given root.
+    pub fn read<P: AsRef<Path>, Q: AsRef<Path>>(root: P, path: Q) -> Result<File> {
+        let file_info = FileInfo::in_workspace(root.as_ref(), path.as_ref(), &Config::default())?;
         file_info.try_into()
     }
 
@@ -207,6 +472,10 @@ impl File {
         self.meta.as_ref()
     }
 
+    pub fn set_meta(&mut self, meta: Option<Meta>) {
+        self.meta = meta;
+    }
+
     pub fn content(&self) -> &[u8] {
         &self.content[..]
     }
@@ -221,3 +490,95 @@ impl TryFrom<FileInfo> for File {
         Ok(File { file_info, meta, content })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_locale() {
+        let test_cases = vec![
+            ("nl", Some(("nl", None, None, "nl"))),
+            (".en.gb", Some(("en", None, Some("gb"), "en.gb"))),
+            ("en-GB", Some(("en", None, Some("gb"), "en.gb"))),
+            ("zh_Hant_TW", Some(("zh", Some("hant"), Some("tw"), "zh.hant.tw"))),
+            ("es-419", Some(("es", None, Some("419"), "es.419"))),
+            ("sr.latn", Some(("sr", Some("latn"), None, "sr.latn"))),
+            ("fil", Some(("fil", None, None, "fil"))),
+            ("xx", None),
+            ("draft", None),
+            ("en.accessible", None),
+            ("en.gb.x", None),
+            ("", None),
+        ];
+        for (input, expected) in test_cases {
+            let locale = input.parse::<FileLocale>().ok();
+            let output = locale
+                .as_ref()
+                .map(|l| (l.language(), l.script(), l.region(), l.as_str()));
+            assert_eq!(output, expected, "input: {}", input);
+        }
+        assert_eq!(
+            "en-GB".parse::<FileLocale>().unwrap(),
+            "en.gb".parse::<FileLocale>().unwrap()
+        );
+
+        let info = FileInfo::new("pages/index.en-GB.md").unwrap();
+        assert_eq!(info.locale().map(|l| l.as_str()), Some("en.gb"));
+        assert!(matches!(
+            FileInfo::new("pages/post.draft.md"),
+            Err(FileInfoError::UnexpectedLocale(_))
+        ));
+        assert!(matches!(
+            FileInfo::new("pages/post.new.md"),
+            Err(FileInfoError::UndeclaredLocale(_))
+        ));
+        assert!(matches!(
+            FileInfo::new("pages/faq.old.md"),
+            Err(FileInfoError::UndeclaredLocale(_))
+        ));
+        let config = Config::parse("locales: [nl, fil-PH]").unwrap();
+        let info = FileInfo::with_config("pages/index.fil.md", &config).unwrap();
+        assert_eq!(info.locale().map(|l| l.as_str()), Some("fil"));
+        assert!(FileInfo::with_config("pages/post.new.md", &config).is_err());
+    }
+
+    #[test]
+    fn test_file_variant() {
+        let config = Config::parse("variants:\n  theme: [accessible, dark]\n  media: [print]\n").unwrap();
+        let test_cases = vec![
+            ("", Some((None, None, None))),
+            ("nl", Some((Some("nl"), None, None))),
+            (".en.gb.Accessible", Some((Some("en.gb"), Some("accessible"), None))),
+            ("dark.print", Some((None, Some("dark"), Some("print")))),
+            ("en.print", Some((Some("en"), None, Some("print")))),
+            ("print.dark", None),
+            ("dark.nl", None),
+            ("en.accessible.dark", None),
+            ("draft", None),
+        ];
+        for (input, expected) in test_cases {
+            let variant = FileVariant::parse(input, &config).ok();
+            let output = variant
+                .as_ref()
+                .map(|v| (v.locale().map(|l| l.as_str()), v.value("theme"), v.value("media")));
+            assert_eq!(output, expected, "input: {}", input);
+        }
+
+        let info = FileInfo::with_config("pages/index.nl-BE.dark.md", &config).unwrap();
+        assert_eq!(info.variant().to_string(), "nl.be.dark");
+        assert_eq!(info.name(), "index");
+        assert!(matches!(
+            FileInfo::with_config("pages/post.draft.md", &config),
+            Err(FileInfoError::UnexpectedVariant(_))
+        ));
+        assert!(FileInfo::new("pages/index.dark.md").is_err());
+
+        let root = Path::new("/tmp/my-pages/site");
+        let info = FileInfo::in_workspace(root, &root.join("pages/foo/bar.nl.md"), &config).unwrap();
+        assert_eq!(info.directory(), Some("/foo"));
+        assert_eq!(info.name(), "bar");
+        assert_eq!(info.path(), "/tmp/my-pages/site/pages/foo/bar.nl.md");
+        assert!(FileInfo::new("/tmp/pages/site/pages/index.md").is_err());
+    }
+}
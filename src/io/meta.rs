@@ -1,12 +1,12 @@
-use std::collections::HashMap;
-
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use indexmap::IndexMap;
 use regex::bytes::Regex;
-use serde_yaml;
 
 use super::{Value, ValueIter};
 use super::path::PathIter;
+use super::yaml;
 
+#[derive(Debug, Clone)]
 pub struct Meta {
     content: Value,
 }
@@ -27,7 +27,7 @@ impl Meta {
         }
     }
 
-    pub fn as_value<'a>(&'a self) -> &'a Value {
+    pub fn as_value(&self) -> &Value {
         &self.content
     }
 
@@ -48,7 +48,7 @@ impl Meta {
     fn extract_html(content: &mut Vec<u8>) -> Result<Option<Meta>> {
         lazy_static! {
             static ref RE: Regex = Regex::new(
-                r"(?m)^\s*<!--\s*[\n\r]+\s*(?P<meta>.+?)\s*[\n\r]+\s*-->\s*\n*(?P<next>.)?"
+                r"(?s)\A\s*<!--\s*[\n\r]+\s*(?P<meta>.+?)\s*[\n\r]+\s*-->\s*\n*(?P<next>.)?"
             )
             .unwrap();
         }
@@ -58,7 +58,7 @@ impl Meta {
     fn extract_markdown(content: &mut Vec<u8>) -> Result<Option<Meta>> {
         lazy_static! {
             static ref RE: Regex = Regex::new(
-                r"(?m)^\s*---\s*[\n\r]+\s*(?P<meta>.+?)\s*[\n\r]+\s*---\s*\n*(?P<next>.)?"
+                r"(?s)\A\s*---\s*[\n\r]+\s*(?P<meta>.+?)\s*[\n\r]+\s*---\s*\n*(?P<next>.)?"
             )
             .unwrap();
         }
@@ -66,66 +66,58 @@ impl Meta {
     }
 
     fn extract_yaml(content: &mut Vec<u8>) -> Result<Option<Meta>> {
-        let m: HashMap<String, serde_yaml::Value> = serde_yaml::from_slice(&content)?;
-        let map: HashMap<String, Value> = m.into_iter().map(|(k, v)| (k, v.into())).collect();
-        drop_first_n_bytes(content, content.len());
+        let content = std::mem::take(content);
         Ok(Some(Meta {
-            content: Value::Mapping(map),
+            content: Meta::parse_yaml(&content)?,
         }))
     }
 
     fn extract_json(content: &mut Vec<u8>) -> Result<Option<Meta>> {
-        let m: HashMap<String, serde_json::Value> = serde_json::from_slice(&content)?;
-        let map: HashMap<String, Value> = m.into_iter().map(|(k, v)| (k, v.into())).collect();
-        drop_first_n_bytes(content, content.len());
+        let m: IndexMap<String, serde_json::Value> = serde_json::from_slice(content)?;
+        let map: IndexMap<String, Value> = m.into_iter().map(|(k, v)| (k, v.into())).collect();
+        content.clear();
         Ok(Some(Meta {
             content: Value::Mapping(map),
         }))
     }
 
+    fn parse_yaml(content: &[u8]) -> Result<Value> {
+        match yaml::parse(std::str::from_utf8(content)?)? {
+            value @ Value::Mapping(_) => Ok(value),
+            _ => Err(anyhow!("metadata must be a mapping")),
+        }
+    }
+
     fn extract_header(re: &Regex, content: &mut Vec<u8>) -> Result<Option<Meta>> {
-        let result = match re
+        let result = re
             .captures(content)
-            .and_then(|m| m.name("meta").and_then(|meta| Some((m, meta))))
-        {
-            None => None,
-            Some((m, meta)) => Some((
-                meta.as_bytes().to_vec(),
-                m.name("next").and_then(|n| Some(n.start())),
-            )),
-        };
+            .and_then(|m| m.name("meta").map(|meta| (m, meta)))
+            .map(|(m, meta)| {
+                (
+                    meta.as_bytes().to_vec(),
+                    // drop everything up to the next content byte,
+                    // or the entire header in case there is no content following it
+                    m.name("next").map(|n| n.start()).unwrap_or_else(|| m.get(0).unwrap().end()),
+                )
+            });
         match result {
             None => Ok(None),
-            Some((raw_content, n_opt)) => {
-                if let Some(n) = n_opt {
-                    drop_first_n_bytes(content, n);
-                }
-                let m: HashMap<String, serde_yaml::Value> = serde_yaml::from_slice(&raw_content)?;
-                let map: HashMap<String, Value> =
-                    m.into_iter().map(|(k, v)| (k, v.into())).collect();
+            Some((raw_content, n)) => {
+                drop_first_n_bytes(content, n);
                 Ok(Some(Meta {
-                    content: Value::Mapping(map),
+                    content: Meta::parse_yaml(&raw_content)?,
                 }))
             }
         }
     }
 }
 
-fn drop_first_n_bytes(vec: &mut Vec<u8>, n: usize) {
-    // We have to make sure vec has enough elements.
-    // You could make the function unsafe and ask the caller to ensure
-    // this condition.
-    assert!(vec.len() >= n);
-
-    unsafe {
-        // We need to update the vector. The values from index `i` to its end
-        // need to be moved at the beginning of the vector.
-        // SAFETY:
-        //  * We have an exclusive reference to the vector. It is both valid for reads and writes.
-        std::ptr::copy(vec.as_ptr().add(n), vec.as_mut_ptr(), n);
-
-        // And update the length of `vec`.
-        // SAFETY: This subtraction is safe because we previously checked that `vec.len() >= n`.
-        vec.set_len(vec.len() - n);
+impl From<Value> for Meta {
+    fn from(content: Value) -> Meta {
+        Meta { content }
     }
 }
+
+fn drop_first_n_bytes(vec: &mut Vec<u8>, n: usize) {
+    vec.drain(..n);
+}
@@ -1,13 +1,21 @@
+mod config;
+pub use config::{Config, CONFIG_FILE};
+
 pub mod data;
 pub use data::{Value, ValueIter};
 
+mod datetime;
+pub use datetime::DateTime;
+
 mod file;
-pub use file::{File, FileInfo, FileFormat, FileKind, FileLocale};
+pub use file::{File, FileInfo, FileFormat, FileKind, FileLocale, FileVariant, VariantDimension};
 
 mod meta;
 pub use meta::Meta;
 
 pub mod path;
 
+pub mod yaml;
+
 mod workspace;
-pub use workspace::{Workspace, FileOrValue};
+pub use workspace::{Workspace, FileOrValue, FileOrValueMatch};
@@ -1,12 +1,12 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
 
 use super::path::{PathComponent, PathIter};
-use super::{File, FileFormat, FileInfo};
+use super::{Config, File, FileFormat, FileInfo, FileLocale, FileVariant, Meta};
 use super::{Value, ValueIter};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 
 pub struct Workspace {
     root: PathBuf,
@@ -14,6 +14,9 @@ pub struct Workspace {
     pages: FileEntry,
     layouts: FileEntry,
     includes: FileEntry,
+    config: Config,
+    // variants found by lookups, from most to least specific
+    chain: Vec<FileVariant>,
 }
 
 impl Workspace {
@@ -22,17 +25,17 @@ impl Workspace {
 
         let assets = list_files(path.join("assets"))?;
 
-        let pages = load_files(path.join("pages"), &|file_info| match file_info.format() {
-            FileFormat::Html | FileFormat::Markdown | FileFormat::Rhai => true,
-            _ => false,
+        let config = Config::read(path)?;
+
+        let pages = load_files(path, path.join("pages"), &config, &|file_info| {
+            matches!(file_info.format(), FileFormat::Html | FileFormat::Markdown | FileFormat::Rhai)
         })?;
 
-        let layouts = load_files(path.join("layouts"), &|file_info| match file_info.format() {
-            FileFormat::Html => true,
-            _ => false,
+        let layouts = load_files(path, path.join("layouts"), &config, &|file_info| {
+            matches!(file_info.format(), FileFormat::Html)
         })?;
 
-        let includes = load_files(path.join("includes"), &|_| true)?;
+        let includes = load_files(path, path.join("includes"), &config, &|_| true)?;
 
         Ok(Workspace {
             root: PathBuf::from(path),
@@ -40,9 +43,80 @@ impl Workspace {
             pages,
             layouts,
             includes,
+            config,
+            chain: vec![FileVariant::default()],
         })
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Variant of the files found by lookups, where files without this variant
+    /// are found using the variant it falls back to, such as the variant of a less
+    /// specific locale, or their default variant, if any.
+    ///
+    /// The metadata of a variant found this way is merged with that of the variants
+    /// it falls back to, such that it only has to define the values it overrides.
+    pub fn variant(&self) -> &FileVariant {
+        &self.chain[0]
+    }
+
+    pub fn set_variant(&mut self, variant: FileVariant) {
+        self.chain = self.config.variant_chain(&variant);
+    }
+
+    /// Locale of the files found by lookups, if any.
+    pub fn locale(&self) -> Option<&FileLocale> {
+        self.variant().locale()
+    }
+
+    /// Locales to render pages in, as declared by the site configuration,
+    /// or else all locales of the pages, layouts and includes of the workspace.
+    pub fn locales(&self) -> Vec<FileLocale> {
+        if let Some(locales) = self.config.locales() {
+            return locales.to_vec();
+        }
+        let mut locales = BTreeSet::new();
+        for entry in [&self.pages, &self.layouts, &self.includes] {
+            collect_locales(entry, &mut locales);
+        }
+        locales.into_iter().collect()
+    }
+
+    /// Variants to render pages in: the default variant, and every combination of
+    /// one of the [`locales`] and the values of the declared variant dimensions,
+    /// leaving out the locale or any of the dimensions.
+    ///
+    /// [`locales`]: Workspace::locales
+    pub fn variants(&self) -> Vec<FileVariant> {
+        self.config.variants(&self.locales())
+    }
+
+    /// Every page to render, once for each of the [`variants`],
+    /// using the most specific variant of the page available for that variant, if any.
+    /// The metadata of that variant is merged with that of the variants it falls back to.
+    ///
+    /// Pages are paired with the variant to render them in, ordered by path and variant.
+    ///
+    /// [`variants`]: Workspace::variants
+    pub fn localized_pages(&mut self) -> Result<Vec<(FileVariant, File)>> {
+        let chains: Vec<_> = self
+            .variants()
+            .into_iter()
+            .map(|variant| {
+                let chain = self.config.variant_chain(&variant);
+                (variant, chain)
+            })
+            .collect();
+        let mut pages = Vec::new();
+        collect_pages(&mut self.pages, &chains, &mut pages)?;
+        pages.sort_by(|(a_variant, a), (b_variant, b)| {
+            (a.info().path(), a_variant).cmp(&(b.info().path(), b_variant))
+        });
+        Ok(pages)
+    }
+
     pub fn root(&self) -> &Path {
         self.root.as_path()
     }
@@ -51,52 +125,83 @@ impl Workspace {
         &self.assets[..]
     }
 
-    pub fn page_or_value<'a, 'b, T>(&'a mut self, t: T) -> Option<FileOrValue<'a>>
+    pub fn page_or_value<'a, 'b, T>(&'a mut self, t: T) -> Result<Option<FileOrValue<'a>>>
     where
         T: Into<PathIter<'b>>,
     {
-        self.page_or_value_iter(t).next()
+        self.page_or_value_iter(t).next().transpose()
     }
 
     pub fn page_or_value_iter<'a, 'b, T>(&'a mut self, t: T) -> FileOrValueIter<'a, 'b>
     where
         T: Into<PathIter<'b>>,
     {
-        FileOrValueIter::new(&mut self.pages, t)
+        FileOrValueIter::new(&mut self.pages, &self.chain, t)
     }
 
-    pub fn layout_or_value<'a, 'b, T>(&'a mut self, t: T) -> Option<FileOrValue<'a>>
+    pub fn layout_or_value<'a, 'b, T>(&'a mut self, t: T) -> Result<Option<FileOrValue<'a>>>
     where
         T: Into<PathIter<'b>>,
     {
-        self.layout_or_value_iter(t).next()
+        self.layout_or_value_iter(t).next().transpose()
     }
 
     pub fn layout_or_value_iter<'a, 'b, T>(&'a mut self, t: T) -> FileOrValueIter<'a, 'b>
     where
         T: Into<PathIter<'b>>,
     {
-        FileOrValueIter::new(&mut self.layouts, t)
+        FileOrValueIter::new(&mut self.layouts, &self.chain, t)
     }
 
-    pub fn include_or_value<'a, 'b, T>(&'a mut self, t: T) -> Option<FileOrValue<'a>>
+    pub fn include_or_value<'a, 'b, T>(&'a mut self, t: T) -> Result<Option<FileOrValue<'a>>>
     where
         T: Into<PathIter<'b>>,
     {
-        self.include_or_value_iter(t).next()
+        self.include_or_value_iter(t).next().transpose()
     }
 
     pub fn include_or_value_iter<'a, 'b, T>(&'a mut self, t: T) -> FileOrValueIter<'a, 'b>
     where
         T: Into<PathIter<'b>>,
     {
-        FileOrValueIter::new(&mut self.includes, t)
+        FileOrValueIter::new(&mut self.includes, &self.chain, t)
+    }
+
+    /// Replace the metadata of the include file found at the given path,
+    /// e.g. with the data generated by running an include script.
+    pub fn set_include_meta<'b, T>(&mut self, t: T, meta: Option<Meta>) -> Result<()>
+    where
+        T: Into<PathIter<'b>>,
+    {
+        let mut entry = &mut self.includes;
+        for component in t.into() {
+            entry = match (component, entry) {
+                (PathComponent::Name(name), FileEntry::Dir(map)) => map
+                    .get_mut(&name.to_lowercase())
+                    .ok_or_else(|| anyhow!("include '{}' not found", name))?,
+                _ => return Err(anyhow!("include path does not refer to a single file")),
+            };
+        }
+        match entry {
+            FileEntry::File(variants) => {
+                variants.resolved.clear();
+                match variants.select(&self.chain) {
+                    Some(file) => {
+                        file.read_or_get_file_mut()?.set_meta(meta);
+                        Ok(())
+                    }
+                    None => Err(anyhow!("include path has no file for the current variant")),
+                }
+            }
+            FileEntry::Dir(_) => Err(anyhow!("include path refers to a directory")),
+        }
     }
 }
 
 enum FileEntry {
-    File(LazyFile),
-    Dir(HashMap<String, FileEntry>),
+    File(Variants),
+    // sorted by name, such that lookups are deterministic
+    Dir(BTreeMap<String, FileEntry>),
 }
 
 enum LazyFile {
@@ -104,17 +209,126 @@ enum LazyFile {
     File(File),
 }
 
+/// All variants of a file, such as `intro.md`, `intro.nl.md` and `intro.nl.accessible.md`.
+#[derive(Default)]
+struct Variants {
+    files: BTreeMap<FileVariant, LazyFile>,
+    // file resolved for a variant, with the metadata of its fallbacks merged into it
+    resolved: BTreeMap<FileVariant, File>,
+}
+
+impl Variants {
+    /// The variants available of the given chain of variants, from most to least specific.
+    fn keys(&self, chain: &[FileVariant]) -> Vec<FileVariant> {
+        chain
+            .iter()
+            .filter(|key| self.files.contains_key(key))
+            .cloned()
+            .collect()
+    }
+
+    /// The most specific variant available of the given chain of variants.
+    fn select(&mut self, chain: &[FileVariant]) -> Option<&mut LazyFile> {
+        let key = self.keys(chain).into_iter().next()?;
+        self.files.get_mut(&key)
+    }
+
+    /// The most specific variant available of the given chain of variants,
+    /// with the metadata of the less specific variants merged into its own.
+    fn resolve(&mut self, chain: &[FileVariant]) -> Result<Option<&File>> {
+        let keys = self.keys(chain);
+        match keys.len() {
+            0 => return Ok(None),
+            1 => return self.files.get_mut(&keys[0]).unwrap().read_or_get_file().map(Some),
+            _ => (),
+        }
+        let variant = chain[0].clone();
+        if !self.resolved.contains_key(&variant) {
+            let mut meta: Option<Value> = None;
+            for key in keys.iter().rev() {
+                let file = self.files.get_mut(key).unwrap().read_or_get_file()?;
+                if let Some(value) = file.meta().map(|m| m.as_value().clone()) {
+                    match meta.as_mut() {
+                        Some(meta) => meta.merge(value),
+                        None => meta = Some(value),
+                    }
+                }
+            }
+            let mut file = self.files.get_mut(&keys[0]).unwrap().read_or_get_file()?.clone();
+            file.set_meta(meta.map(Meta::from));
+            self.resolved.insert(variant.clone(), file);
+        }
+        Ok(self.resolved.get(&variant))
+    }
+}
+
+fn collect_locales(entry: &FileEntry, locales: &mut BTreeSet<FileLocale>) {
+    match entry {
+        FileEntry::File(variants) => {
+            locales.extend(variants.files.keys().filter_map(|v| v.locale()).cloned())
+        }
+        FileEntry::Dir(map) => {
+            for entry in map.values() {
+                collect_locales(entry, locales);
+            }
+        }
+    }
+}
+
+/// Collect the file of every page resolved for every variant, given with its chain of variants.
+fn collect_pages(
+    entry: &mut FileEntry,
+    chains: &[(FileVariant, Vec<FileVariant>)],
+    pages: &mut Vec<(FileVariant, File)>,
+) -> Result<()> {
+    match entry {
+        FileEntry::File(variants) => {
+            for (variant, chain) in chains {
+                if let Some(file) = variants.resolve(chain)? {
+                    pages.push((variant.clone(), file.clone()));
+                }
+            }
+        }
+        FileEntry::Dir(map) => {
+            for entry in map.values_mut() {
+                collect_pages(entry, chains, pages)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 impl LazyFile {
+    pub fn info(&self) -> &FileInfo {
+        match self {
+            LazyFile::FileInfo(info) => info,
+            LazyFile::File(file) => file.info(),
+        }
+    }
+
     pub fn read_or_get_file(&mut self) -> Result<&File> {
         match self {
             LazyFile::File(file) => Ok(file),
             LazyFile::FileInfo(info) => {
-                let file = info.clone().try_into()?;
+                let file = File::try_from(info.clone())
+                    .with_context(|| format!("read {}", info.path()))?;
                 *self = LazyFile::File(file);
                 self.read_or_get_file()
             },
         }
     }
+
+    pub fn read_or_get_file_mut(&mut self) -> Result<&mut File> {
+        if let LazyFile::FileInfo(info) = self {
+            let file = File::try_from(info.clone())
+                .with_context(|| format!("read {}", info.path()))?;
+            *self = LazyFile::File(file);
+        }
+        match self {
+            LazyFile::File(file) => Ok(file),
+            LazyFile::FileInfo(_) => unreachable!("file is read"),
+        }
+    }
 }
 
 pub enum FileOrValue<'a> {
@@ -122,10 +336,20 @@ pub enum FileOrValue<'a> {
     Value(&'a Value),
 }
 
+/// A file or value found by a lookup, along with where it was found.
+pub struct FileOrValueMatch<'a> {
+    /// Resolved path of the match, e.g. `blog.post.title` when looking up `blog.*.title`.
+    pub path: String,
+    /// The file found, or the file of which the metadata contains the value found.
+    pub file: &'a File,
+    pub value: FileOrValue<'a>,
+}
+
 enum FileEntryOrValueInnerState<'a, 'b> {
     None,
     FileEntry(FileEntryState<'a, 'b>),
-    ValueIter(ValueIter<'a, 'b>),
+    // file of which the metadata is iterated, and the resolved path of that file
+    ValueIter(&'a File, String, ValueIter<'a, 'b>),
 }
 
 struct FileEntryState<'a, 'b> {
@@ -133,10 +357,44 @@ struct FileEntryState<'a, 'b> {
     pub entry_ref: &'a mut FileEntry,
     pub path_index: usize,
     pub recursive: bool,
+    // names of the entries leading from the root to this entry
+    pub trail: Vec<String>,
 }
 
-fn load_files<P: AsRef<Path>>(dir: P, filter: &dyn Fn(&FileInfo) -> bool) -> Result<FileEntry> {
-    let mut files = HashMap::new();
+/// Entries of the directory in lexical order of their names, files before directories.
+fn sorted_entries(map: &mut BTreeMap<String, FileEntry>) -> Vec<(&String, &mut FileEntry)> {
+    let mut entries: Vec<_> = map.iter_mut().collect();
+    // stable sort, keeping the lexical order within files and directories
+    entries.sort_by_key(|(_, entry)| matches!(entry, FileEntry::Dir(_)));
+    entries
+}
+
+/// Iterator state for an entry of a directory of which the trail is given.
+fn child_state<'a, 'b>(
+    trail: &[String],
+    name: &str,
+    entry_ref: &'a mut FileEntry,
+    path: Vec<PathComponent<'b>>,
+    recursive: bool,
+) -> FileOrValueIterInner<'a, 'b> {
+    let mut trail = trail.to_vec();
+    trail.push(name.to_owned());
+    FileOrValueIterInner::new(FileEntryOrValueInnerState::FileEntry(FileEntryState {
+        path,
+        entry_ref,
+        path_index: 0,
+        recursive,
+        trail,
+    }))
+}
+
+fn load_files<P: AsRef<Path>>(
+    root: &Path,
+    dir: P,
+    config: &Config,
+    filter: &dyn Fn(&FileInfo) -> bool,
+) -> Result<FileEntry> {
+    let mut files = BTreeMap::new();
 
     let dir = dir.as_ref();
     if !dir.exists() {
@@ -147,15 +405,31 @@ fn load_files<P: AsRef<Path>>(dir: P, filter: &dyn Fn(&FileInfo) -> bool) -> Res
         let entry = entry?;
         let path = entry.path();
         if path.is_dir() {
-            let dir = load_files(&path, filter)?;
+            let dir = load_files(root, &path, config, filter)?;
             match path.file_name().and_then(|n| n.to_str()) {
                 Some(dir_name) => files.insert(dir_name.to_lowercase(), dir),
                 None => return Err(anyhow!("failed to get dirname for dir entry")),
             };
         } else {
-            let file_info: FileInfo = (&path).try_into()?;
+            let file_info = FileInfo::in_workspace(root, &path, config)
+                .with_context(|| format!("file {}", path.display()))?;
             if filter(&file_info) {
-                files.insert(file_info.name().to_lowercase(), FileEntry::File(LazyFile::FileInfo(file_info)));
+                let entry = files
+                    .entry(file_info.name().to_lowercase())
+                    .or_insert_with(|| FileEntry::File(Variants::default()));
+                if let FileEntry::File(variants) = entry {
+                    let path = file_info.path().to_owned();
+                    let other = variants
+                        .files
+                        .insert(file_info.variant().clone(), LazyFile::FileInfo(file_info));
+                    if let Some(other) = other {
+                        return Err(anyhow!(
+                            "files {} and {} have the same name and variant",
+                            other.info().path(),
+                            path
+                        ));
+                    }
+                }
             }
         }
     }
@@ -175,12 +449,12 @@ fn list_files<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
     loop {
         match dirs_to_read.pop() {
             None => break,
-            Some(dir) => { 
+            Some(dir) => {
                 for entry in fs::read_dir(dir)? {
                     let entry = entry?;
                     let path = entry.path();
                     if path.is_dir() {
-                        dirs_to_read.push(PathBuf::from(path));
+                        dirs_to_read.push(path);
                     } else {
                         let path = path.strip_prefix(root)?;
                         file_paths.push(PathBuf::from(path));
@@ -190,11 +464,21 @@ fn list_files<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
         }
     }
 
+    file_paths.sort();
     Ok(file_paths)
 }
 
+/// Iterator over all files and values found at a path, which can contain wildcards.
+///
+/// Matches are found breadth first, where the entries of a directory are visited
+/// in lexical order of their names, files before directories,
+/// and the values within a file in the order of [`ValueIter`].
+///
+/// Files are read once they are visited, where the iteration ends with the error
+/// of a file which can't be read or of which the metadata can't be parsed.
 pub struct FileOrValueIter<'a, 'b> {
     stack: VecDeque<FileOrValueIterInner<'a, 'b>>,
+    chain: Vec<FileVariant>,
 }
 
 struct FileOrValueIterInner<'a, 'b> {
@@ -202,7 +486,7 @@ struct FileOrValueIterInner<'a, 'b> {
 }
 
 impl<'a, 'b> FileOrValueIter<'a, 'b> {
-    fn new<T>(entry: &'a mut FileEntry, t: T) -> FileOrValueIter<'a, 'b>
+    fn new<T>(entry: &'a mut FileEntry, chain: &[FileVariant], t: T) -> FileOrValueIter<'a, 'b>
     where
         T: Into<PathIter<'b>>,
     {
@@ -212,24 +496,29 @@ impl<'a, 'b> FileOrValueIter<'a, 'b> {
                 path_index: 0,
                 entry_ref: entry,
                 recursive: false,
+                trail: Vec::new(),
             }));
         let mut stack = VecDeque::with_capacity(1);
         stack.push_front(root_value_iter);
-        FileOrValueIter { stack }
+        FileOrValueIter {
+            stack,
+            chain: chain.to_vec(),
+        }
     }
-}
 
-impl<'a, 'b> Iterator for FileOrValueIter<'a, 'b> {
-    type Item = FileOrValue<'a>;
+    /// Iterate over the matches, rather than only the files and values found.
+    pub fn matches(mut self) -> impl Iterator<Item = Result<FileOrValueMatch<'a>>> + use<'a, 'b> {
+        std::iter::from_fn(move || self.next_match())
+    }
 
-    fn next(&mut self) -> Option<FileOrValue<'a>> {
+    fn next_match(&mut self) -> Option<Result<FileOrValueMatch<'a>>> {
         let mut inner_stack = VecDeque::new();
         loop {
             if self.stack.is_empty() {
                 return None;
             }
-            let result = self.stack[0].next_value(&mut inner_stack);
-            if inner_stack.len() > 0 {
+            let result = self.stack[0].next_value(&mut inner_stack, &self.chain);
+            if !inner_stack.is_empty() {
                 self.stack.append(&mut inner_stack);
             }
             match result {
@@ -237,12 +526,24 @@ impl<'a, 'b> Iterator for FileOrValueIter<'a, 'b> {
                     self.stack.pop_front();
                     continue;
                 }
+                Some(Err(err)) => {
+                    self.stack.clear();
+                    return Some(Err(err));
+                }
                 Some(value) => return Some(value),
             }
         }
     }
 }
 
+impl<'a, 'b> Iterator for FileOrValueIter<'a, 'b> {
+    type Item = Result<FileOrValue<'a>>;
+
+    fn next(&mut self) -> Option<Result<FileOrValue<'a>>> {
+        self.next_match().map(|m| m.map(|m| m.value))
+    }
+}
+
 impl<'a, 'b> FileOrValueIterInner<'a, 'b> {
     pub fn new(state: FileEntryOrValueInnerState<'a, 'b>) -> FileOrValueIterInner<'a, 'b> {
         FileOrValueIterInner { state }
@@ -251,144 +552,286 @@ impl<'a, 'b> FileOrValueIterInner<'a, 'b> {
     fn next_value(
         &mut self,
         stack: &mut VecDeque<FileOrValueIterInner<'a, 'b>>,
-    ) -> Option<FileOrValue<'a>> {
+        chain: &[FileVariant],
+    ) -> Option<Result<FileOrValueMatch<'a>>> {
         let state = std::mem::replace(&mut self.state, FileEntryOrValueInnerState::None);
         match state {
             FileEntryOrValueInnerState::None => None,
-            FileEntryOrValueInnerState::ValueIter(mut it) => match it.next() {
+            FileEntryOrValueInnerState::ValueIter(file, file_path, mut it) => match it.next_with_path() {
                 None => {
                     self.state = FileEntryOrValueInnerState::None;
                     None
                 }
-                Some(value) => {
-                    self.state = FileEntryOrValueInnerState::ValueIter(it);
-                    return Some(FileOrValue::Value(value));
+                Some((path, value)) => {
+                    let path = if path.is_empty() {
+                        file_path.clone()
+                    } else {
+                        format!("{}.{}", file_path, path)
+                    };
+                    self.state = FileEntryOrValueInnerState::ValueIter(file, file_path, it);
+                    Some(Ok(FileOrValueMatch {
+                        path,
+                        file,
+                        value: FileOrValue::Value(value),
+                    }))
                 }
             },
-            FileEntryOrValueInnerState::FileEntry(mut state) => {
-                while state.path_index >= state.path.len() {
-                    match state.path[state.path_index] {
-                        PathComponent::Name(name) => match state.entry_ref {
-                            FileEntry::File(file) => match file.read_or_get_file().ok().and_then(|file| file.meta()) {
-                                None => return None,
-                                Some(meta) => {
-                                    let mut path = Vec::new();
-                                    if state.recursive {
-                                        path.push(PathComponent::AnyRecursive);
-                                    }
-                                    path.extend(state.path.into_iter().skip(state.path_index));
-                                    let value_it =
-                                        meta.value_iter(PathIter::wrap(path.into_iter()));
-                                    stack.push_back(FileOrValueIterInner::new(
-                                        FileEntryOrValueInnerState::ValueIter(value_it),
-                                    ));
-                                    return None;
+            FileEntryOrValueInnerState::FileEntry(mut state) => loop {
+                if state.path_index >= state.path.len() {
+                    // path is fully consumed: a file is a match,
+                    // while a directory only matters when recursive (e.g. `foo.**`)
+                    let trail = state.trail.join(".");
+                    return match state.entry_ref {
+                        FileEntry::File(variants) => variants.resolve(chain).transpose().map(|file| {
+                            file.map(|file| FileOrValueMatch {
+                                path: trail,
+                                file,
+                                value: FileOrValue::File(file),
+                            })
+                        }),
+                        FileEntry::Dir(map) => {
+                            if state.recursive {
+                                for (name, entry) in sorted_entries(map) {
+                                    stack.push_back(child_state(&state.trail, name, entry, Vec::new(), true));
                                 }
-                            },
-                            FileEntry::Dir(map) => {
-                                let name = name.to_lowercase();
-                                if !state.recursive {
-                                    match map.get_mut(&name) {
-                                        None => return None,
-                                        Some(entry) => {
-                                            state.entry_ref = entry;
-                                            state.path_index += 1;
-                                            state.recursive = false;
-                                        }
-                                    }
-                                } else {
-                                    for (entry_name, entry) in map.iter_mut() {
-                                        if entry_name == &name {
-                                            stack.push_front(FileOrValueIterInner::new(
-                                                FileEntryOrValueInnerState::FileEntry(
-                                                    FileEntryState {
-                                                        path: state.path[state.path_index + 1..]
-                                                            .to_vec(),
-                                                        entry_ref: entry,
-                                                        path_index: 0,
-                                                        recursive: false,
-                                                    },
-                                                ),
-                                            ));
-                                        } else {
-                                            stack.push_back(FileOrValueIterInner::new(
-                                                FileEntryOrValueInnerState::FileEntry(
-                                                    FileEntryState {
-                                                        path: state.path[state.path_index..]
-                                                            .to_vec(),
-                                                        entry_ref: entry,
-                                                        path_index: 0,
-                                                        recursive: true,
-                                                    },
-                                                ),
-                                            ));
-                                        }
-                                    }
-                                    return None;
+                            }
+                            None
+                        }
+                    };
+                }
+                match state.path[state.path_index] {
+                    PathComponent::Name(name) => match state.entry_ref {
+                        FileEntry::File(variants) => match variants.resolve(chain) {
+                            Ok(Some(file)) if file.meta().is_some() => {
+                                let mut path = Vec::new();
+                                if state.recursive {
+                                    path.push(PathComponent::AnyRecursive);
                                 }
+                                path.extend(state.path.into_iter().skip(state.path_index));
+                                let value_it = file.meta().unwrap().value_iter(PathIter::wrap(path.into_iter()));
+                                stack.push_back(FileOrValueIterInner::new(
+                                    FileEntryOrValueInnerState::ValueIter(file, state.trail.join("."), value_it),
+                                ));
+                                return None;
                             }
+                            Err(err) => return Some(Err(err)),
+                            _ => return None,
                         },
-                        PathComponent::Any => match state.entry_ref {
-                            FileEntry::File(file) => match file.read_or_get_file().ok().and_then(|file| file.meta()) {
-                                None => {
-                                    self.state = FileEntryOrValueInnerState::None;
-                                    return None;
-                                }
-                                Some(meta) => {
-                                    let it = state.path.into_iter().skip(state.path_index);
-                                    let value_it = meta.value_iter(PathIter::wrap(it));
-                                    stack.push_back(FileOrValueIterInner::new(
-                                        FileEntryOrValueInnerState::ValueIter(value_it),
-                                    ));
-                                    return None;
+                        FileEntry::Dir(map) => {
+                            let name = name.to_lowercase();
+                            if !state.recursive {
+                                match map.get_mut(&name) {
+                                    None => return None,
+                                    Some(entry) => {
+                                        state.entry_ref = entry;
+                                        state.path_index += 1;
+                                        state.recursive = false;
+                                        state.trail.push(name);
+                                    }
                                 }
-                            },
-                            FileEntry::Dir(map) => {
-                                for entry in map.values_mut() {
-                                    stack.push_back(FileOrValueIterInner::new(
-                                        FileEntryOrValueInnerState::FileEntry(FileEntryState {
-                                            path: state.path[state.path_index + 1..].to_vec(),
-                                            entry_ref: entry,
-                                            path_index: 0,
-                                            recursive: false,
-                                        }),
-                                    ));
+                            } else {
+                                for (entry_name, entry) in sorted_entries(map) {
+                                    if entry_name == &name {
+                                        let path = state.path[state.path_index + 1..].to_vec();
+                                        stack.push_front(child_state(&state.trail, entry_name, entry, path, false));
+                                    } else {
+                                        let path = state.path[state.path_index..].to_vec();
+                                        stack.push_back(child_state(&state.trail, entry_name, entry, path, true));
+                                    }
                                 }
                                 return None;
                             }
-                        },
-                        PathComponent::AnyRecursive => match state.entry_ref {
-                            FileEntry::File(file) => match file.read_or_get_file().ok().and_then(|file| file.meta()) {
-                                None => {
-                                    return None;
-                                }
-                                Some(meta) => {
+                        }
+                    },
+                    PathComponent::Any | PathComponent::AnyRecursive => {
+                        let recursive = state.path[state.path_index] == PathComponent::AnyRecursive;
+                        match state.entry_ref {
+                            FileEntry::File(variants) => match variants.resolve(chain) {
+                                Ok(Some(file)) if file.meta().is_some() => {
                                     let it = state.path.into_iter().skip(state.path_index);
-                                    let value_it = meta.value_iter(PathIter::wrap(it));
+                                    let value_it = file.meta().unwrap().value_iter(PathIter::wrap(it));
                                     stack.push_back(FileOrValueIterInner::new(
-                                        FileEntryOrValueInnerState::ValueIter(value_it),
+                                        FileEntryOrValueInnerState::ValueIter(file, state.trail.join("."), value_it),
                                     ));
                                     return None;
                                 }
+                                Err(err) => return Some(Err(err)),
+                                _ => return None,
                             },
                             FileEntry::Dir(map) => {
-                                for entry in map.values_mut() {
-                                    stack.push_back(FileOrValueIterInner::new(
-                                        FileEntryOrValueInnerState::FileEntry(FileEntryState {
-                                            path: state.path[state.path_index + 1..].to_vec(),
-                                            entry_ref: entry,
-                                            path_index: 0,
-                                            recursive: true,
-                                        }),
-                                    ));
+                                for (name, entry) in sorted_entries(map) {
+                                    let path = state.path[state.path_index + 1..].to_vec();
+                                    stack.push_back(child_state(&state.trail, name, entry, path, recursive));
                                 }
                                 return None;
                             }
-                        },
+                        }
                     }
                 }
-                None
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::CONFIG_FILE;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_matches() {
+        let root = TempDir::new("workspace");
+        fs::create_dir_all(root.join("includes/blog")).unwrap();
+        fs::write(
+            root.join("includes/blog/Hello.md"),
+            "---\ntitle: Hello\ntags: [a, b]\n---\n",
+        )
+        .unwrap();
+        let mut workspace = Workspace::read(&root).unwrap();
+
+        let test_cases = vec![
+            ("blog.hello", vec!["blog.hello"]),
+            ("BLOG.*.title", vec!["blog.hello.title"]),
+            ("blog.hello.tags.*", vec!["blog.hello.tags.0", "blog.hello.tags.1"]),
+            ("**.hello.tags.1", vec!["blog.hello.tags.1"]),
+        ];
+        for (path, expected) in test_cases {
+            let matches: Vec<_> = workspace
+                .include_or_value_iter(path)
+                .matches()
+                .collect::<Result<_>>()
+                .unwrap();
+            let paths: Vec<_> = matches.iter().map(|m| m.path.as_str()).collect();
+            assert_eq!(paths, expected, "path: {}", path);
+            assert!(matches
+                .iter()
+                .all(|m| m.file.info().path().ends_with("Hello.md")));
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let root = TempDir::new("workspace-order");
+        fs::create_dir_all(root.join("includes/a/c")).unwrap();
+        fs::write(root.join("includes/b.yml"), "z: 1\ny: 2\nx: [3, 2, 1]\n").unwrap();
+        fs::write(root.join("includes/a/d.md"), "").unwrap();
+        fs::write(root.join("includes/a/c/e.md"), "").unwrap();
+        fs::write(root.join("includes/a/b.md"), "").unwrap();
+        fs::write(root.join("includes/c.json"), r#"{"z": 1, "y": {"b": 2, "a": 3}}"#).unwrap();
+        let mut workspace = Workspace::read(&root).unwrap();
+
+        let test_cases = vec![
+            ("a.*", vec!["a.b", "a.d"]),
+            ("a.**", vec!["a.b", "a.d", "a.c.e"]),
+            ("b.*", vec!["b.z", "b.y", "b.x"]),
+            ("b.x.*", vec!["b.x.0", "b.x.1", "b.x.2"]),
+            ("c.**", vec!["c.z", "c.y", "c.y.b", "c.y.a"]),
+        ];
+        for (path, expected) in test_cases {
+            let paths: Vec<_> = workspace
+                .include_or_value_iter(path)
+                .matches()
+                .map(|m| m.unwrap().path)
+                .collect();
+            assert_eq!(paths, expected, "path: {}", path);
+        }
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let root = TempDir::new("workspace-errors");
+        fs::create_dir_all(root.join("includes")).unwrap();
+        fs::write(root.join("includes/card.yml"), "title: [unclosed\n").unwrap();
+        fs::write(root.join("includes/intro.md"), "---\ntitle: Intro\n---\n").unwrap();
+        let mut workspace = Workspace::read(&root).unwrap();
+
+        for path in ["card", "card.title"] {
+            let err = workspace.include_or_value(path).err().expect(path);
+            assert!(format!("{:#}", err).contains("card.yml"), "{:#}", err);
+        }
+        let results: Vec<_> = workspace.include_or_value_iter("*.title").collect();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        assert!(workspace.include_or_value("intro.title").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_locale_variants() {
+        let root = TempDir::new("workspace-locale");
+        fs::create_dir_all(root.join("includes")).unwrap();
+        fs::write(root.join("includes/footer.yml"), "text: Bye\nyear: 2021\n").unwrap();
+        fs::write(root.join("includes/footer.nl.yml"), "text: Doei\n").unwrap();
+        fs::write(root.join("includes/intro.nl.md"), "Hallo").unwrap();
+        let mut workspace = Workspace::read(&root).unwrap();
+
+        let test_cases = vec![
+            (None, vec!["footer.text  footer.yml", "footer.year  footer.yml"]),
+            (
+                Some("nl"),
+                vec!["footer.text  footer.nl.yml", "footer.year  footer.nl.yml", "intro  intro.nl.md"],
+            ),
+            (Some("fr"), vec!["footer.text  footer.yml", "footer.year  footer.yml"]),
+        ];
+        for (locale, expected) in test_cases {
+            workspace.set_variant(FileVariant::from(locale.map(|l| l.parse().unwrap())));
+            let mut matches = Vec::new();
+            for path in ["footer.*", "intro"] {
+                for m in workspace.include_or_value_iter(path).matches() {
+                    let m = m.unwrap();
+                    let name = m.file.info().path().rsplit('/').next().unwrap();
+                    matches.push(format!("{}  {}", m.path, name));
+                }
+            }
+            assert_eq!(matches, expected, "locale: {:?}", locale);
+        }
+        assert_eq!(workspace.localized_pages().unwrap().len(), 0);
+        let locales: Vec<_> = workspace.locales().iter().map(|l| l.as_str().to_owned()).collect();
+        assert_eq!(locales, vec!["nl"]);
+
+        fs::write(root.join("includes/footer.NL.yml"), "text: Dag\n").unwrap();
+        assert!(Workspace::read(&root).is_err());
+    }
+
+    #[test]
+    fn test_locale_fallbacks() {
+        let root = TempDir::new("workspace-fallbacks");
+        fs::create_dir_all(root.join("includes")).unwrap();
+        fs::write(
+            root.join("includes/strings.yml"),
+            "locale:\n  name: American English\n  short: us\nsite:\n  name: Example\n",
+        )
+        .unwrap();
+        fs::write(root.join("includes/strings.en.gb.yml"), "locale:\n  name: British\n").unwrap();
+        fs::write(root.join("includes/intro.en.md"), "Hello").unwrap();
+        let mut workspace = Workspace::read(&root).unwrap();
+        workspace.set_variant(FileVariant::from(Some("en-GB".parse().unwrap())));
+
+        let mut matches = Vec::new();
+        for path in ["strings.locale.*", "strings.site.name", "intro"] {
+            for m in workspace.include_or_value_iter(path).matches() {
+                let m = m.unwrap();
+                let value = match m.value {
+                    FileOrValue::File(_) => String::new(),
+                    FileOrValue::Value(value) => value.to_text().unwrap(),
+                };
+                matches.push(format!("{}  {}", m.path, value));
             }
         }
+        assert_eq!(
+            matches,
+            vec![
+                "strings.locale.name  British",
+                "strings.locale.short  us",
+                "strings.site.name  Example",
+                "intro  ",
+            ]
+        );
+
+        fs::write(root.join(CONFIG_FILE), "fallbacks:\n  en.gb: []\n").unwrap();
+        let mut workspace = Workspace::read(&root).unwrap();
+        workspace.set_variant(FileVariant::from(Some("en-GB".parse().unwrap())));
+        assert!(workspace.include_or_value("intro").unwrap().is_none());
+        assert!(workspace.include_or_value("strings.site.name").unwrap().is_some());
     }
 }
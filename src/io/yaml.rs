@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use indexmap::IndexMap;
+use regex::Regex;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, TScalarStyle, TokenType};
+
+use super::{DateTime, Value};
+
+/// Parse the first document of the given YAML content, `Null` if it has none.
+///
+/// Unlike generic YAML loaders, the style of scalars is taken into account,
+/// such that only plain scalars are parsed as numbers, booleans, null or timestamps,
+/// while quoted scalars, e.g. `"2021-02-01"`, always are strings.
+pub fn parse(content: &str) -> Result<Value> {
+    let mut loader = Loader::default();
+    Parser::new(content.chars()).load(&mut loader, false)?;
+    if let Some(err) = loader.error {
+        return Err(err);
+    }
+    Ok(loader.document.unwrap_or(Value::Null))
+}
+
+#[derive(Default)]
+struct Loader {
+    document: Option<Value>,
+    // sequences and mappings being loaded, along with their anchor, where mappings also
+    // hold the key of the value being loaded, which is `None` for complex keys
+    stack: Vec<(Value, Option<Option<String>>, usize)>,
+    anchors: HashMap<usize, Value>,
+    error: Option<anyhow::Error>,
+}
+
+impl Loader {
+    fn push(&mut self, value: Value, anchor: usize) {
+        if anchor > 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+        match self.stack.last_mut() {
+            None => self.document = Some(value),
+            Some((Value::Sequence(seq), _, _)) => seq.push(value),
+            Some((Value::Mapping(map), key, _)) => match key.take() {
+                Some(Some(key)) => {
+                    map.insert(key, value);
+                }
+                // complex keys can't be looked up, and thus their entries are left out
+                Some(None) => (),
+                None => *key = Some(mapping_key(value)),
+            },
+            Some(_) => unreachable!("only sequences and mappings are stacked"),
+        }
+    }
+
+    fn pop(&mut self) {
+        if let Some((value, _, anchor)) = self.stack.pop() {
+            self.push(value, anchor);
+        }
+    }
+}
+
+impl MarkedEventReceiver for Loader {
+    fn on_event(&mut self, event: Event, _: Marker) {
+        if self.error.is_some() {
+            return;
+        }
+        match event {
+            Event::SequenceStart(anchor) => self.stack.push((Value::Sequence(Vec::new()), None, anchor)),
+            Event::MappingStart(anchor) => self.stack.push((Value::Mapping(IndexMap::new()), None, anchor)),
+            Event::SequenceEnd | Event::MappingEnd => self.pop(),
+            Event::Scalar(s, style, anchor, tag) => {
+                let value = scalar(s, style, tag.as_ref());
+                self.push(value, anchor);
+            }
+            Event::Alias(anchor) => match self.anchors.get(&anchor) {
+                Some(value) => self.push(value.clone(), 0),
+                None => self.error = Some(anyhow!("unknown anchor")),
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Resolve a scalar to a value: quoted scalars and scalars tagged as `!!str` are strings,
+/// while plain scalars are resolved like YAML does.
+fn scalar(s: String, style: TScalarStyle, tag: Option<&TokenType>) -> Value {
+    let is_str = matches!(tag, Some(TokenType::Tag(handle, suffix)) if handle == "!!" && suffix == "str");
+    if style != TScalarStyle::Plain || is_str {
+        return Value::String(s);
+    }
+    match s.as_str() {
+        "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Boolean(true),
+        "false" | "False" | "FALSE" => return Value::Boolean(false),
+        _ => (),
+    }
+    let digits = s.trim_start_matches(['-', '+']);
+    // leading zeros make a string rather than a number, e.g. a zip code
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && digits.bytes().all(|b| b.is_ascii_digit());
+    if !leading_zero {
+        if let Some(n) = integer(&s) {
+            return Value::Integer(n);
+        }
+        if let Some(n) = float(&s) {
+            return Value::Float(n);
+        }
+    }
+    match DateTime::parse(&s) {
+        Some(d) => Value::DateTime(d),
+        None => Value::String(s),
+    }
+}
+
+fn integer(s: &str) -> Option<i64> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let n = match unsigned.get(..2) {
+        Some("0x") => i64::from_str_radix(&unsigned[2..], 16).ok()?,
+        Some("0o") => i64::from_str_radix(&unsigned[2..], 8).ok()?,
+        Some("0b") => i64::from_str_radix(&unsigned[2..], 2).ok()?,
+        _ if unsigned.bytes().all(|b| b.is_ascii_digit()) => unsigned.parse().ok()?,
+        _ => return None,
+    };
+    Some(if negative { -n } else { n })
+}
+
+fn float(s: &str) -> Option<f64> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^[-+]?(\.\d+|\d+(\.\d*)?)([eE][-+]?\d+)?$").unwrap();
+    }
+    match s.trim_start_matches('+') {
+        ".inf" | ".Inf" | ".INF" => Some(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => Some(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => Some(f64::NAN),
+        _ if RE.is_match(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Mapping keys are strings, other scalar keys are converted to their text.
+fn mapping_key(key: Value) -> Option<String> {
+    match key {
+        Value::String(s) => Some(s),
+        Value::Null => Some(String::new()),
+        Value::Sequence(_) | Value::Mapping(_) => None,
+        key => key.to_text(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let value = parse(
+            "a: 2021-2-1\nb: \"2021-2-1\"\nc: '2021-02-01'\nd: !!str 2021-02-01\ne: [1, 0x10, 1.5, 007, true, ~, \"1\"]\nf: &x {g: h}\ni: *x\n",
+        )
+        .unwrap();
+        let text = |path: &str| value.value(path).and_then(|v| v.to_text());
+        assert!(matches!(value.value("a"), Some(Value::DateTime(_))));
+        assert_eq!(text("a").as_deref(), Some("2021-02-01"));
+        assert!(matches!(value.value("b"), Some(Value::String(s)) if s == "2021-2-1"));
+        assert!(matches!(value.value("c"), Some(Value::String(_))));
+        assert!(matches!(value.value("d"), Some(Value::String(_))));
+        let items: Vec<_> = value.value_iter("e.*").map(|v| format!("{:?}", v)).collect();
+        assert_eq!(
+            items,
+            vec![
+                "Integer(1)",
+                "Integer(16)",
+                "Float(1.5)",
+                "String(\"007\")",
+                "Boolean(true)",
+                "Null",
+                "String(\"1\")",
+            ]
+        );
+        assert_eq!(text("i.g").as_deref(), Some("h"));
+
+        assert!(matches!(parse("").unwrap(), Value::Null));
+        assert!(parse("a: [b").is_err());
+        let value = parse("? [a]\n: b\nc: d\n").unwrap();
+        assert_eq!(value.to_mapping().map(|m| m.len()), Some(1));
+    }
+}
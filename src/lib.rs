@@ -1,10 +1,12 @@
-use std::error::Error;
-
 #[macro_use]
 extern crate lazy_static;
 
-pub fn really_complicated_code(a: u8, b: u8) -> Result<u8, Box<dyn Error>> {
-    Ok(a + b)
-}
-
+pub mod env;
 pub mod io;
+pub mod publish;
+pub mod render;
+pub mod scaffold;
+pub mod serve;
+
+#[cfg(test)]
+mod testing;
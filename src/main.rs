@@ -1,6 +1,299 @@
-fn main() {
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, Result};
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Parser, Subcommand};
+
+use tsg::env;
+use tsg::io::{FileLocale, FileOrValue, FileVariant, Value, Workspace};
+use tsg::publish::{self, AssetMode, BuildOptions, LocaleStyle, OutputMapper, PathStyle};
+use tsg::render;
+use tsg::scaffold;
+use tsg::serve::{self, ServeOptions, DEFAULT_POLL_INTERVAL};
+
+/// Tiny Site Generator, a static site generator.
+#[derive(Parser)]
+#[command(name = "tsg", version)]
+struct Cli {
+    /// Print more information, use twice for even more
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build the website into the publish directory
+    Build(BuildArgs),
+    /// Build and serve the website locally, rebuilding and reloading it on changes
+    Serve(ServeArgs),
+    /// Create a new workspace from a template, or add a new page to a workspace
+    New(NewArgs),
+    /// Print the version, features and script limits of tsg, and the shell used by Bash includes
+    ///
+    /// Use `-v` to also list all embedded dependencies with their version and license.
+    Env,
+    /// Print every file and value matched by an include, page or layout path, e.g. `blog.*.title`
+    Query(QueryArgs),
+}
+
+#[derive(Args)]
+struct BuildArgs {
+    /// Root directory of the workspace
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    /// Publish directory [default: <ROOT>/publish]
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+
+    /// Remove all content of the publish directory before building
+    #[arg(long)]
+    clean: bool,
+
+    /// Only build the localized pages of the given locale(s), all locales by default
+    #[arg(short, long = "locale", value_name = "LOCALE", value_delimiter = ',')]
+    locales: Vec<FileLocale>,
+
+    /// Publish pages as `foo/bar/index.html` instead of `foo/bar.html`
+    #[arg(long)]
+    pretty_urls: bool,
+
+    /// Publish localized pages within a directory of their locale, or using their locale as suffix
+    #[arg(long, value_enum, default_value_t = LocaleStyle::Directory)]
+    locale_style: LocaleStyle,
+
+    /// Mirror assets by copying, hard linking or reflinking them
+    #[arg(long, value_enum, default_value_t = AssetMode::Copy)]
+    assets: AssetMode,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    #[command(flatten)]
+    build: BuildArgs,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    address: IpAddr,
+
+    /// Port to listen on
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+}
+
+#[derive(Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct NewArgs {
+    #[command(subcommand)]
+    command: Option<NewCommand>,
+
+    /// Directory of the new workspace, which has to be empty or not exist yet
+    #[arg(required = true)]
+    dir: Option<PathBuf>,
+
+    /// Template of the new workspace
+    #[arg(short, long, default_value = scaffold::DEFAULT_TEMPLATE, value_parser = PossibleValuesParser::new(scaffold::templates()))]
+    template: String,
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    /// Directory of the workspace to look up the path in
+    #[arg(value_parser = ["includes", "pages", "layouts"])]
+    dir: String,
+
+    /// Path to look up, where `*` matches any name and `**` any number of names
+    path: String,
+
+    /// Look up the files of the given variant, e.g. `nl` or `nl.accessible`,
+    /// falling back to less specific variants
+    #[arg(short = 'l', long, visible_alias = "locale")]
+    variant: Option<String>,
+
+    /// Root directory of the workspace
+    #[arg(long, default_value = ".")]
+    root: PathBuf,
+}
+
+#[derive(Subcommand)]
+enum NewCommand {
+    /// Add a new page to the workspace, e.g. `tsg new page blog/hello.md`
+    Page {
+        /// Path of the page, relative to the pages directory
+        path: String,
+
+        /// Root directory of the workspace
+        #[arg(long, default_value = ".")]
+        root: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Command::Build(args) => build(args, cli.verbose),
+        Command::Serve(args) => serve(args),
+        Command::New(args) => new(args, cli.verbose),
+        Command::Query(args) => query(args),
+        Command::Env => {
+            print_env(cli.verbose);
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+impl BuildArgs {
+    fn options(&self) -> BuildOptions {
+        let out_dir = self
+            .out
+            .clone()
+            .unwrap_or_else(|| self.root.join(publish::DEFAULT_OUT_DIR));
+        let mut options = BuildOptions::new(&self.root, out_dir);
+        options.clean = self.clean;
+        options.locales = self.locales.clone();
+        options.mapper = OutputMapper::new(
+            if self.pretty_urls {
+                PathStyle::Pretty
+            } else {
+                PathStyle::Html
+            },
+            self.locale_style,
+        );
+        options.asset_mode = self.assets;
+        options
+    }
+}
+
+fn build(args: &BuildArgs, verbose: u8) -> Result<()> {
+    let options = args.options();
+    let report = publish::build(&options)?;
+    if verbose > 0 {
+        for page in report.pages.iter() {
+            println!("page  {}", page);
+        }
+    }
+    if verbose > 1 {
+        println!(
+            "assets  {} updated, {} unchanged, {} removed",
+            report.assets.updated, report.assets.unchanged, report.assets.removed
+        );
+    }
     println!(
-        "I'm using the library: {:?}",
-        tsg::really_complicated_code(1, 2)
+        "built {} page(s) and {} asset(s) into {}",
+        report.pages.len(),
+        report.assets.updated + report.assets.unchanged,
+        options.out_dir.display()
     );
-}
\ No newline at end of file
+    Ok(())
+}
+
+fn serve(args: &ServeArgs) -> Result<()> {
+    let options = ServeOptions {
+        build: args.build.options(),
+        address: SocketAddr::new(args.address, args.port),
+        poll_interval: DEFAULT_POLL_INTERVAL,
+    };
+    serve::serve(options, |line| eprintln!("{}", line))
+}
+
+fn new(args: &NewArgs, verbose: u8) -> Result<()> {
+    match (&args.command, &args.dir) {
+        (Some(NewCommand::Page { path, root }), _) => {
+            let path = scaffold::new_page(root, path)?;
+            println!("created page {}", path.display());
+        }
+        (None, Some(dir)) => {
+            let files = scaffold::new_workspace(dir, &args.template)?;
+            if verbose > 0 {
+                for file in files.iter() {
+                    println!("file  {}", file.display());
+                }
+            }
+            println!(
+                "created workspace {} using the {} template",
+                dir.display(),
+                args.template
+            );
+        }
+        (None, None) => unreachable!("directory is required"),
+    }
+    Ok(())
+}
+
+fn print_env(verbose: u8) {
+    println!("tsg {}", env::VERSION);
+    let features = env::features();
+    if features.is_empty() {
+        println!("features  none");
+    } else {
+        println!("features  {}", features.join(", "));
+    }
+    match render::detect_shell() {
+        Some(shell) => println!("shell  {} ({})", shell.path.display(), shell.version),
+        None => println!("shell  bash not found, Bash includes can't be run"),
+    }
+    for (name, limit) in render::script_limits() {
+        if limit == 0 {
+            println!("rhai  {}  unlimited", name);
+        } else {
+            println!("rhai  {}  {}", name, limit);
+        }
+    }
+    if verbose > 0 {
+        for dep in env::dependencies() {
+            println!("dependency  {} {}  {}", dep.name, dep.version, dep.license);
+        }
+    }
+}
+
+fn query(args: &QueryArgs) -> Result<()> {
+    let mut workspace = Workspace::read(&args.root)?;
+    if let Some(variant) = &args.variant {
+        let variant = FileVariant::parse(variant, workspace.config())?;
+        workspace.set_variant(variant);
+    }
+    let it = match args.dir.as_str() {
+        "includes" => workspace.include_or_value_iter(args.path.as_str()),
+        "pages" => workspace.page_or_value_iter(args.path.as_str()),
+        _ => workspace.layout_or_value_iter(args.path.as_str()),
+    };
+    let mut count = 0;
+    for m in it.matches() {
+        let m = m?;
+        count += 1;
+        let source = m.file.info().path();
+        match m.value {
+            FileOrValue::File(_) => println!("file  {}  {}", m.path, source),
+            FileOrValue::Value(value) => {
+                println!("value  {}  {}  {}", m.path, source, preview(value))
+            }
+        }
+    }
+    if count == 0 {
+        return Err(anyhow!("no {} found at '{}'", args.dir, args.path));
+    }
+    Ok(())
+}
+
+/// Short description of the value, only showing scalar values in full.
+fn preview(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::String(s) => format!("{:?}", s),
+        Value::Sequence(seq) => format!("[{} item(s)]", seq.len()),
+        Value::Mapping(map) => format!("{{{} key(s)}}", map.len()),
+        value => value.to_text().unwrap_or_default(),
+    }
+}
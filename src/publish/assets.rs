@@ -0,0 +1,238 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::io::Workspace;
+
+/// Directory within the workspace holding the state TSG keeps between builds,
+/// which is never published.
+pub const CACHE_DIR: &str = ".tsg";
+
+/// The way assets are mirrored into the publish directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum AssetMode {
+    #[default]
+    Copy,
+    Hardlink,
+    /// Copy-on-write clone of the asset, falling back to a regular copy
+    /// in case the file system does not support it.
+    Reflink,
+}
+
+/// What happened to the assets while mirroring them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MirrorStats {
+    pub updated: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// Mirror all assets of the workspace into the publish directory,
+/// keeping their directory structure.
+///
+/// Only assets of which the size or modification time changed are written,
+/// and assets mirrored by a previous publish which no longer exist are removed.
+pub fn mirror_assets<P: AsRef<Path>>(
+    workspace: &Workspace,
+    out_dir: P,
+    mode: AssetMode,
+) -> Result<MirrorStats> {
+    let out_dir = out_dir.as_ref();
+    let assets_dir = workspace.root().join("assets");
+    let mut stats = MirrorStats::default();
+
+    let mut assets: Vec<&PathBuf> = workspace.assets().iter().collect();
+    assets.sort();
+    for asset in assets.iter() {
+        let src = assets_dir.join(asset);
+        let dst = out_dir.join(asset);
+        if is_unchanged(&src, &dst)? {
+            stats.unchanged += 1;
+            continue;
+        }
+        mirror_file(&src, &dst, mode).with_context(|| format!("mirror asset {}", src.display()))?;
+        stats.updated += 1;
+    }
+
+    let manifest = manifest_path(workspace.root(), out_dir)?;
+    let current: HashSet<&Path> = assets.iter().map(|p| p.as_path()).collect();
+    for stale in read_manifest(&manifest)? {
+        if current.contains(stale.as_path()) {
+            continue;
+        }
+        let path = out_dir.join(&stale);
+        if path.is_file() {
+            fs::remove_file(&path)
+                .with_context(|| format!("remove stale asset {}", path.display()))?;
+            remove_empty_parents(&path, out_dir);
+            stats.removed += 1;
+        }
+    }
+    write_manifest(&manifest, &assets)?;
+
+    Ok(stats)
+}
+
+fn is_unchanged(src: &Path, dst: &Path) -> Result<bool> {
+    let src_meta = fs::metadata(src)?;
+    let dst_meta = match fs::metadata(dst) {
+        Ok(meta) => meta,
+        Err(_) => return Ok(false),
+    };
+    Ok(src_meta.len() == dst_meta.len() && src_meta.modified()? == dst_meta.modified()?)
+}
+
+fn mirror_file(src: &Path, dst: &Path, mode: AssetMode) -> Result<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // never write through an existing (hard) link into the source
+    if dst.exists() {
+        fs::remove_file(dst)?;
+    }
+    match mode {
+        AssetMode::Hardlink => {
+            // a hard link shares the metadata of its source
+            return Ok(fs::hard_link(src, dst)?);
+        }
+        AssetMode::Reflink => {
+            reflink_copy::reflink_or_copy(src, dst)?;
+        }
+        AssetMode::Copy => {
+            fs::copy(src, dst)?;
+        }
+    }
+    // keep the modification time of the source, so unchanged assets can be detected
+    let modified = fs::metadata(src)?.modified()?;
+    fs::File::options()
+        .write(true)
+        .open(dst)?
+        .set_modified(modified)?;
+    Ok(())
+}
+
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d == root || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+/// Path of the file listing the assets mirrored into the given publish directory by the last build,
+/// such that assets removed from the workspace can be removed from the publish directory,
+/// without touching any of the other published files. It is kept in the cache directory,
+/// one per publish directory, such that it doesn't get published itself.
+fn manifest_path(root: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    std::path::absolute(out_dir)?.hash(&mut hasher);
+    Ok(root
+        .join(CACHE_DIR)
+        .join("assets")
+        .join(format!("{:016x}", hasher.finish())))
+}
+
+fn read_manifest(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.split('/').collect())
+        .collect())
+}
+
+fn write_manifest(path: &Path, assets: &[&PathBuf]) -> Result<()> {
+    if assets.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut content = String::new();
+    for asset in assets {
+        let components: Vec<_> = asset
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        content.push_str(&components.join("/"));
+        content.push('\n');
+    }
+    fs::write(path, content).with_context(|| format!("write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn write(path: PathBuf, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_mirror_assets() {
+        let root = TempDir::new("assets-workspace");
+        let out = TempDir::new("assets-out");
+        write(root.join("assets/main.css"), "body {}");
+        write(root.join("assets/img/logo.svg"), "<svg/>");
+        write(out.join("index.html"), "<p>index</p>");
+
+        let stats = mirror_assets(&Workspace::read(&root).unwrap(), &out, AssetMode::Copy).unwrap();
+        assert_eq!(
+            stats,
+            MirrorStats {
+                updated: 2,
+                unchanged: 0,
+                removed: 0
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("img/logo.svg")).unwrap(),
+            "<svg/>"
+        );
+
+        let stats = mirror_assets(&Workspace::read(&root).unwrap(), &out, AssetMode::Copy).unwrap();
+        assert_eq!(
+            stats,
+            MirrorStats {
+                updated: 0,
+                unchanged: 2,
+                removed: 0
+            }
+        );
+
+        fs::remove_file(root.join("assets/img/logo.svg")).unwrap();
+        write(root.join("assets/main.css"), "body { margin: 0 }");
+        let stats =
+            mirror_assets(&Workspace::read(&root).unwrap(), &out, AssetMode::Hardlink).unwrap();
+        assert_eq!(
+            stats,
+            MirrorStats {
+                updated: 1,
+                unchanged: 0,
+                removed: 1
+            }
+        );
+        assert!(!out.join("img").exists());
+        assert!(out.join("index.html").exists());
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 2);
+        assert!(root.join(CACHE_DIR).join("assets").is_dir());
+        assert_eq!(
+            fs::read_to_string(out.join("main.css")).unwrap(),
+            "body { margin: 0 }"
+        );
+    }
+}
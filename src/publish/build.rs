@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use super::{mirror_assets, AssetMode, MirrorStats, OutputMapper};
+use crate::io::{FileLocale, Workspace};
+use crate::render::Renderer;
+
+/// Publish directory used unless specified otherwise, relative to the workspace root.
+pub const DEFAULT_OUT_DIR: &str = "publish";
+
+/// Options of a single build of a workspace.
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Root directory of the workspace.
+    pub root: PathBuf,
+    /// Publish directory, where the pages and assets are written to.
+    pub out_dir: PathBuf,
+    /// Remove all content of the publish directory prior to building.
+    pub clean: bool,
+    /// Locales of the localized pages to build, all locales if none are given.
+    /// Pages which aren't localized are always built.
+    pub locales: Vec<FileLocale>,
+    pub mapper: OutputMapper,
+    pub asset_mode: AssetMode,
+}
+
+/// Result of a successful build.
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    /// Output paths of all pages written, relative to the publish directory.
+    pub pages: Vec<String>,
+    pub assets: MirrorStats,
+}
+
+impl BuildOptions {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(root: P, out_dir: Q) -> BuildOptions {
+        BuildOptions {
+            root: root.as_ref().to_path_buf(),
+            out_dir: out_dir.as_ref().to_path_buf(),
+            clean: false,
+            locales: Vec::new(),
+            mapper: OutputMapper::default(),
+            asset_mode: AssetMode::default(),
+        }
+    }
+
+    fn includes_locale(&self, locale: Option<&FileLocale>) -> bool {
+        match locale {
+            None => true,
+            Some(locale) => self.locales.is_empty() || self.locales.contains(locale),
+        }
+    }
+}
+
+/// Render all pages of the workspace into the publish directory,
+/// and mirror all of its assets.
+pub fn build(options: &BuildOptions) -> Result<BuildReport> {
+    if !options.root.is_dir() {
+        return Err(anyhow!(
+            "workspace {} is not a directory",
+            options.root.display()
+        ));
+    }
+    let mut workspace = Workspace::read(&options.root)
+        .with_context(|| format!("read workspace {}", options.root.display()))?;
+    if options.clean {
+        clean(&options.root, &options.out_dir)?;
+    }
+
+    let pages = workspace.localized_pages()?;
+
+    let mut renderer = Renderer::new(workspace);
+    renderer.set_output_mapper(options.mapper);
+
+    let mut report = BuildReport::default();
+    // source of every page written, to detect pages overwriting one another
+    let mut sources: HashMap<String, String> = HashMap::new();
+    for (variant, page) in pages {
+        if !options.includes_locale(variant.locale()) {
+            continue;
+        }
+        let source = page.info().path().to_owned();
+        let rendered = renderer
+            .render_pages(&page, &variant)
+            .with_context(|| match variant.is_default() {
+                true => format!("render page {}", source),
+                false => format!("render page {} in variant {}", source, variant),
+            })?;
+        for rendered in rendered {
+            if !options.includes_locale(rendered.variant.locale()) {
+                continue;
+            }
+            if let Some(other) = sources.insert(rendered.path.clone(), source.clone()) {
+                return Err(anyhow!(
+                    "page '{}' is published by both {} and {}",
+                    rendered.path,
+                    other,
+                    source
+                ));
+            }
+            let path = options.out_dir.join(&rendered.path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, rendered.content)
+                .with_context(|| format!("write page {}", path.display()))?;
+            report.pages.push(rendered.path);
+        }
+    }
+
+    report.assets = mirror_assets(&renderer.workspace(), &options.out_dir, options.asset_mode)?;
+    Ok(report)
+}
+
+/// Remove the publish directory, refusing to do so
+/// in case the workspace is contained within it.
+fn clean(root: &Path, out_dir: &Path) -> Result<()> {
+    if !out_dir.exists() {
+        return Ok(());
+    }
+    let root = root.canonicalize()?;
+    let out_dir = out_dir.canonicalize()?;
+    if root.starts_with(&out_dir) {
+        return Err(anyhow!(
+            "refusing to clean {}, as it contains the workspace",
+            out_dir.display()
+        ));
+    }
+    fs::remove_dir_all(&out_dir).with_context(|| format!("clean {}", out_dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::CONFIG_FILE;
+    use crate::testing::{workspace, TempDir};
+
+    #[test]
+    fn test_build() {
+        let root = workspace(
+            "site",
+            &[
+                ("pages/index.md", "# Index"),
+                ("pages/index.nl.md", "# Start"),
+                ("pages/about.nl.md", "# Over"),
+                ("pages/contact.fr.md", "# Contact"),
+                ("pages/blog/post.html", "<p>post</p>"),
+                ("assets/main.css", "body {}"),
+            ],
+        );
+        let out = root.join(DEFAULT_OUT_DIR);
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("stale.html"), "").unwrap();
+
+        let mut options = BuildOptions::new(&root, &out);
+        options.clean = true;
+        options.locales = vec!["NL".parse().unwrap()];
+        let report = build(&options).unwrap();
+        assert_eq!(
+            report.pages,
+            vec![
+                "nl/about.html",
+                "blog/post.html",
+                "nl/blog/post.html",
+                "index.html",
+                "nl/index.html"
+            ]
+        );
+        assert_eq!(report.assets.updated, 1);
+        assert_eq!(
+            fs::read_to_string(out.join("nl/about.html")).unwrap(),
+            "<h1>Over</h1>\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("index.html")).unwrap(),
+            "<h1>Index</h1>\n"
+        );
+        assert!(!out.join("stale.html").exists());
+
+        options.out_dir = root.to_path_buf();
+        assert!(build(&options).is_err());
+
+        fs::write(root.join(CONFIG_FILE), "locales: [de]").unwrap();
+        options.out_dir = out.clone();
+        options.locales = Vec::new();
+        let report = build(&options).unwrap();
+        assert_eq!(
+            report.pages,
+            vec!["blog/post.html", "de/blog/post.html", "index.html", "de/index.html"]
+        );
+
+        fs::write(root.join(CONFIG_FILE), "locales: [de]\nvariants:\n  theme: [dark]\n").unwrap();
+        fs::write(root.join("pages/index.dark.md"), "# Dark").unwrap();
+        fs::remove_dir_all(root.join("pages/blog")).unwrap();
+        let report = build(&options).unwrap();
+        assert_eq!(
+            report.pages,
+            vec!["dark/index.html", "de/dark/index.html", "index.html", "de/index.html"]
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("de/dark/index.html")).unwrap(),
+            "<h1>Dark</h1>\n"
+        );
+    }
+
+    #[test]
+    fn test_build_generated_locale() {
+        let root = workspace(
+            "generated-locale",
+            &[
+                ("pages/index.nl.md", "# Start"),
+                (
+                    "pages/gen.rhai",
+                    "fn generate(generator) { generator.html(\"x.html\", \"x\", #{ locale: \"nl\" }) }",
+                ),
+            ],
+        );
+        let out = root.join(DEFAULT_OUT_DIR);
+        let report = build(&BuildOptions::new(&root, &out)).unwrap();
+        assert_eq!(report.pages, vec!["nl/x.html", "nl/index.html"]);
+    }
+
+    #[test]
+    fn test_build_nested_root() {
+        let dir = TempDir::new("build-nested");
+        let root = dir.join("my-pages/pages/site");
+        fs::create_dir_all(root.join("pages/foo")).unwrap();
+        fs::write(root.join("pages/foo/bar.md"), "# Bar").unwrap();
+        let report = build(&BuildOptions::new(&root, root.join(DEFAULT_OUT_DIR))).unwrap();
+        assert_eq!(report.pages, vec!["foo/bar.html"]);
+    }
+
+    #[test]
+    fn test_build_error() {
+        let root = workspace(
+            "error",
+            &[
+                ("pages/index.md", "<include>foo</include>"),
+                ("includes/foo.html", "<include>bar</include>"),
+            ],
+        );
+        let err = build(&BuildOptions::new(&root, root.join(DEFAULT_OUT_DIR))).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            format!(
+                "render page {}: include 'foo': include 'bar' not found",
+                root.join("pages/index.md").display()
+            )
+        );
+    }
+}
@@ -0,0 +1,8 @@
+mod assets;
+pub use assets::{mirror_assets, AssetMode, MirrorStats, CACHE_DIR};
+
+mod build;
+pub use build::{build, BuildOptions, BuildReport, DEFAULT_OUT_DIR};
+
+mod output;
+pub use output::{LocaleStyle, OutputMapper, PathStyle, INDEX_FILE};
@@ -0,0 +1,234 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+
+use crate::io::{FileInfo, FileVariant};
+
+/// Name of the file served for a directory.
+pub const INDEX_FILE: &str = "index.html";
+
+/// The way pages are mapped to HTML files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathStyle {
+    /// `pages/foo/bar.md` is published as `foo/bar.html`.
+    #[default]
+    Html,
+    /// `pages/foo/bar.md` is published as `foo/bar/index.html`,
+    /// such that it can be linked to as `/foo/bar/`.
+    Pretty,
+}
+
+/// The way localized pages, and other variants of pages, are mapped to HTML files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LocaleStyle {
+    /// `pages/index.nl.html` is published as `nl/index.html`,
+    /// and `pages/index.nl.accessible.html` as `nl/accessible/index.html`.
+    #[default]
+    Directory,
+    /// `pages/index.nl.html` is published as `index.nl.html`,
+    /// and `pages/index.nl.accessible.html` as `index.nl.accessible.html`.
+    Suffix,
+}
+
+impl FromStr for PathStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<PathStyle> {
+        match s.to_lowercase().as_str() {
+            "html" => Ok(PathStyle::Html),
+            "pretty" => Ok(PathStyle::Pretty),
+            _ => Err(anyhow!("unknown path style '{}'", s)),
+        }
+    }
+}
+
+/// Maps pages to their path within the publish directory, and the URL they are served at.
+///
+/// All output paths are relative to the publish directory and use `/` as separator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputMapper {
+    path_style: PathStyle,
+    locale_style: LocaleStyle,
+}
+
+impl OutputMapper {
+    pub fn new(path_style: PathStyle, locale_style: LocaleStyle) -> OutputMapper {
+        OutputMapper {
+            path_style,
+            locale_style,
+        }
+    }
+
+    pub fn path_style(&self) -> PathStyle {
+        self.path_style
+    }
+
+    pub fn locale_style(&self) -> LocaleStyle {
+        self.locale_style
+    }
+
+    /// Output path of the page file.
+    pub fn page_path(&self, info: &FileInfo) -> String {
+        self.localized_page_path(info, info.variant())
+    }
+
+    /// Output path of the page file when rendered in the given variant,
+    /// which differs from the variant of the file when the page has no file of that variant,
+    /// e.g. `pages/index.md` rendered in `nl`.
+    pub fn localized_page_path(&self, info: &FileInfo, variant: &FileVariant) -> String {
+        let mut components: Vec<&str> = info
+            .directory()
+            .map(|dir| dir.split(['/', '\\']).filter(|c| !c.is_empty()).collect())
+            .unwrap_or_default();
+        let name = info.name();
+        let path = match self.path_style {
+            PathStyle::Pretty if name.to_lowercase() != "index" => {
+                components.push(name);
+                components.push(INDEX_FILE);
+                components.join("/")
+            }
+            _ => {
+                let file_name = format!("{}.html", name);
+                components.push(&file_name);
+                components.join("/")
+            }
+        };
+        self.localized_path(&path, variant)
+    }
+
+    /// Output path of a page published at the given path,
+    /// such as the pages generated by scripts, for the given variant.
+    pub fn localized_path(&self, path: &str, variant: &FileVariant) -> String {
+        let path = path.trim_start_matches('/');
+        if variant.is_default() {
+            return path.to_owned();
+        }
+        match self.locale_style {
+            LocaleStyle::Directory => format!("{}/{}", variant.parts().join("/"), path),
+            LocaleStyle::Suffix => match path.rsplit_once('.') {
+                Some((stem, ext)) if !ext.contains('/') => format!("{}.{}.{}", stem, variant, ext),
+                _ => format!("{}.{}", path, variant),
+            },
+        }
+    }
+
+    /// URL of the page published at the given output path,
+    /// omitting the name of index files for pretty paths.
+    pub fn url(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        match self.path_style {
+            PathStyle::Pretty
+                if path == INDEX_FILE || path.ends_with(&format!("/{}", INDEX_FILE)) =>
+            {
+                format!("/{}", &path[..path.len() - INDEX_FILE.len()])
+            }
+            _ => format!("/{}", path),
+        }
+    }
+
+    /// URL of the page file.
+    pub fn page_url(&self, info: &FileInfo) -> String {
+        self.url(&self.page_path(info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_mapper() {
+        let test_cases = vec![
+            (
+                PathStyle::Html,
+                LocaleStyle::Directory,
+                "pages/index.md",
+                "index.html",
+                "/index.html",
+            ),
+            (
+                PathStyle::Html,
+                LocaleStyle::Directory,
+                "pages/foo/bar.md",
+                "foo/bar.html",
+                "/foo/bar.html",
+            ),
+            (
+                PathStyle::Html,
+                LocaleStyle::Directory,
+                "pages/index.nl.html",
+                "nl/index.html",
+                "/nl/index.html",
+            ),
+            (
+                PathStyle::Html,
+                LocaleStyle::Suffix,
+                "pages/foo/bar.nl.md",
+                "foo/bar.nl.html",
+                "/foo/bar.nl.html",
+            ),
+            (
+                PathStyle::Pretty,
+                LocaleStyle::Directory,
+                "pages/index.md",
+                "index.html",
+                "/",
+            ),
+            (
+                PathStyle::Pretty,
+                LocaleStyle::Directory,
+                "pages/foo/bar.md",
+                "foo/bar/index.html",
+                "/foo/bar/",
+            ),
+            (
+                PathStyle::Pretty,
+                LocaleStyle::Directory,
+                "pages/foo/index.nl.md",
+                "nl/foo/index.html",
+                "/nl/foo/",
+            ),
+            (
+                PathStyle::Pretty,
+                LocaleStyle::Suffix,
+                "pages/foo/bar.nl.md",
+                "foo/bar/index.nl.html",
+                "/foo/bar/index.nl.html",
+            ),
+        ];
+        for (path_style, locale_style, page, expected_path, expected_url) in test_cases {
+            let mapper = OutputMapper::new(path_style, locale_style);
+            let info = FileInfo::new(page).unwrap();
+            assert_eq!(mapper.page_path(&info), expected_path, "page: {}", page);
+            assert_eq!(mapper.page_url(&info), expected_url, "page: {}", page);
+        }
+    }
+
+    #[test]
+    fn test_output_mapper_localized_path() {
+        let nl = FileVariant::from(Some("nl".parse().unwrap()));
+        let nl_accessible = FileVariant::new(
+            nl.locale().cloned(),
+            vec![("theme".to_owned(), "accessible".to_owned())],
+        );
+        let mapper = OutputMapper::new(PathStyle::Html, LocaleStyle::Directory);
+        assert_eq!(
+            mapper.localized_path("blog/1.html", &FileVariant::default()),
+            "blog/1.html"
+        );
+        assert_eq!(mapper.localized_path("/blog/1.html", &nl), "nl/blog/1.html");
+        assert_eq!(
+            mapper.localized_path("blog/1.html", &nl_accessible),
+            "nl/accessible/blog/1.html"
+        );
+        let mapper = OutputMapper::new(PathStyle::Html, LocaleStyle::Suffix);
+        assert_eq!(mapper.localized_path("blog/1.html", &nl), "blog/1.nl.html");
+        assert_eq!(
+            mapper.localized_path("blog/1.html", &nl_accessible),
+            "blog/1.nl.accessible.html"
+        );
+
+        let info = FileInfo::new("pages/blog/1.md").unwrap();
+        assert_eq!(mapper.localized_page_path(&info, &nl), "blog/1.nl.html");
+    }
+}
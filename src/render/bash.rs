@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use super::context::MetaContext;
+use crate::io::{File, Value};
+
+// root of the metadata variables, e.g. `TSG_META_TITLE`
+const META_VAR: &str = "TSG_META";
+const INCLUDE_PREFIX: &str = "TSG_INCLUDE_";
+
+/// The shell used to run Bash includes.
+#[derive(Debug, Clone)]
+pub struct Shell {
+    pub path: PathBuf,
+    /// First line reported by `bash --version`.
+    pub version: String,
+}
+
+/// Look up the `bash` executable within the `PATH`, as used by [`run`].
+pub fn detect_shell() -> Option<Shell> {
+    let path = std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join("bash"))
+        .find(|path| path.is_file())?;
+    let output = Command::new(&path).arg("--version").output().ok()?;
+    let version = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+    Some(Shell { path, version })
+}
+
+/// Includes requested by the script, as pairs of variable name and include path,
+/// where the variable `TSG_INCLUDE_FOO_BAR` requests the include `foo.bar`,
+/// and a double underscore stands for an underscore within a name, e.g. `TSG_INCLUDE_FOO__BAR` for `foo_bar`.
+///
+/// Variables mentioned in comments are not requested.
+pub fn include_paths(file: &File) -> Vec<(String, String)> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\bTSG_INCLUDE_(?P<path>[A-Za-z0-9_]+)").unwrap();
+    }
+    let content = strip_comments(&String::from_utf8_lossy(file.content()));
+    let mut paths: Vec<(String, String)> = Vec::new();
+    for m in RE.captures_iter(&content) {
+        let name = format!("{}{}", INCLUDE_PREFIX, &m["path"]);
+        if paths.iter().any(|(n, _)| n == &name) {
+            continue;
+        }
+        let path = m["path"]
+            .to_lowercase()
+            .split("__")
+            .map(|part| {
+                part.split('_')
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(".")
+            })
+            .collect::<Vec<_>>()
+            .join("_");
+        paths.push((name, path));
+    }
+    paths
+}
+
+/// The script without its comments, which start at a `#` that begins a word outside of quotes,
+/// and run until the end of the line.
+fn strip_comments(script: &str) -> String {
+    let mut stripped = String::with_capacity(script.len());
+    let mut quote: Option<char> = None;
+    let mut comment = false;
+    let mut escaped = false;
+    let mut prev = '\n';
+    for c in script.chars() {
+        if comment {
+            if c == '\n' {
+                comment = false;
+                stripped.push(c);
+            }
+            prev = c;
+            continue;
+        }
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => (),
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '#') if prev.is_whitespace() => {
+                comment = true;
+                prev = c;
+                continue;
+            }
+            _ => (),
+        }
+        stripped.push(c);
+        prev = c;
+    }
+    stripped
+}
+
+/// Environment variables for all primitive metadata values of the context,
+/// where `TSG_META_FOO_BAR` holds the value of `foo.bar` as found innermost-first.
+pub fn meta_env(context: &MetaContext) -> HashMap<String, String> {
+    let mut env = HashMap::new();
+    // outer layers first, such that inner layers overwrite the values they shadow
+    for (_, value) in context.layers() {
+        flatten(META_VAR, value, &mut env);
+    }
+    env
+}
+
+fn flatten(name: &str, value: &Value, env: &mut HashMap<String, String>) {
+    match value {
+        Value::Null => (),
+        Value::Sequence(values) => {
+            for (index, value) in values.iter().enumerate() {
+                flatten(&format!("{}_{}", name, index), value, env);
+            }
+        }
+        Value::Mapping(map) => {
+            for (key, value) in map {
+                flatten(&format!("{}_{}", name, env_name(key)), value, env);
+            }
+        }
+        value => {
+            env.insert(name.to_owned(), value.to_text().unwrap_or_default());
+        }
+    }
+}
+
+fn env_name(key: &str) -> String {
+    key.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Run the script using `bash` within a clean environment,
+/// only containing the given variables and the `PATH` of the host.
+///
+/// The output printed to STDOUT is returned, without its trailing newline(s).
+pub fn run<P, I>(file: &File, dir: P, env: I) -> Result<String>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = (String, String)>,
+{
+    let path = file.info().path();
+    // the script is run from the given directory, so its path can't be relative
+    let script = fs::canonicalize(path).with_context(|| format!("{}: resolve script", path))?;
+    let mut cmd = Command::new("bash");
+    cmd.arg(script).current_dir(dir).env_clear().envs(env);
+    if let Some(host_path) = std::env::var_os("PATH") {
+        cmd.env("PATH", host_path);
+    }
+    let output = cmd
+        .output()
+        .with_context(|| format!("{}: failed to run bash", path))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!(
+            "{}: script failed ({}): {}",
+            path,
+            output.status,
+            stderr.trim()
+        ));
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("{}: read output as utf-8", path))?;
+    Ok(stdout.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
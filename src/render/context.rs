@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Result};
+
+use crate::io::path::{PathComponent, PathIter};
+use crate::io::Value;
+
+/// The kind of file (or other source) which defined a layer of metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaLayer {
+    Layout,
+    Page,
+    Include,
+}
+
+impl MetaLayer {
+    fn from_name(name: &str) -> Option<MetaLayer> {
+        Some(match name.to_lowercase().as_str() {
+            "layout" => MetaLayer::Layout,
+            "page" => MetaLayer::Page,
+            "include" => MetaLayer::Include,
+            _ => return None,
+        })
+    }
+}
+
+/// Stack of metadata layers, from the outer layer (e.g. a layout)
+/// to the inner layer (e.g. the include currently rendered).
+///
+/// Values are looked up innermost-first, such that the metadata of
+/// an inner layer shadows the metadata defined by the outer layers.
+#[derive(Debug, Clone, Default)]
+pub struct MetaContext {
+    layers: Vec<(MetaLayer, Value)>,
+}
+
+impl MetaContext {
+    pub fn new() -> MetaContext {
+        MetaContext::default()
+    }
+
+    pub fn push(&mut self, layer: MetaLayer, value: Value) {
+        self.layers.push((layer, value));
+    }
+
+    pub fn pop(&mut self) -> Option<(MetaLayer, Value)> {
+        self.layers.pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.layers.truncate(len);
+    }
+
+    /// All layers, from the outermost to the innermost layer.
+    pub fn layers(&self) -> impl DoubleEndedIterator<Item = (MetaLayer, &Value)> {
+        self.layers.iter().map(|(layer, value)| (*layer, value))
+    }
+
+    /// Look up the value for the given path, innermost layer first.
+    pub fn value<'a, 'b, T>(&'a self, t: T) -> Option<&'a Value>
+    where
+        T: Into<PathIter<'b>>,
+    {
+        self.value_in(None, t)
+    }
+
+    /// Look up the value for the given path, innermost layer first,
+    /// only taking into account the layers of the given kind if one is given.
+    pub fn value_in<'a, 'b, T>(&'a self, layer: Option<MetaLayer>, t: T) -> Option<&'a Value>
+    where
+        T: Into<PathIter<'b>>,
+    {
+        let path: Vec<PathComponent<'b>> = t.into().collect();
+        self.layers
+            .iter()
+            .rev()
+            .filter(|(l, _)| layer.map(|layer| layer == *l).unwrap_or(true))
+            .find_map(|(_, value)| value.value(PathIter::wrap(path.clone().into_iter())))
+    }
+
+    /// Look up a metadata path as used by includes and scripts.
+    ///
+    /// The path is either relative to all layers (`title` or `$.title`),
+    /// or explicitly relative to the innermost layer of a given kind
+    /// (`$page.title`, `$layout.title` or `$include.title`).
+    pub fn lookup(&self, path: &str) -> Result<Option<&Value>> {
+        let path = path.trim();
+        let (layer, path) = match path.strip_prefix('$') {
+            None => (None, path),
+            Some(path) => {
+                let (name, path) = path.split_once('.').unwrap_or((path, ""));
+                match name {
+                    "" => (None, path),
+                    name => match MetaLayer::from_name(name) {
+                        Some(layer) => (Some(layer), path),
+                        None => return Err(anyhow!("unknown metadata layer '${}'", name)),
+                    },
+                }
+            }
+        };
+        Ok(self.value_in(layer, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(pairs: &[(&str, &str)]) -> Value {
+        Value::Mapping(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), Value::from(*v)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_lookup_shadowing() {
+        let mut context = MetaContext::new();
+        context.push(
+            MetaLayer::Layout,
+            mapping(&[("title", "layout"), ("site", "tsg")]),
+        );
+        context.push(MetaLayer::Page, mapping(&[("title", "page")]));
+        context.push(MetaLayer::Include, mapping(&[("intro", "include")]));
+
+        let test_cases = vec![
+            ("title", Some("page")),
+            ("$.title", Some("page")),
+            ("$.site", Some("tsg")),
+            ("$.intro", Some("include")),
+            ("$layout.title", Some("layout")),
+            ("$page.title", Some("page")),
+            ("$page.site", None),
+            ("$include.title", None),
+            ("$.missing", None),
+        ];
+        for (path, expected) in test_cases {
+            let value = context.lookup(path).unwrap().and_then(Value::as_str);
+            assert_eq!(value, expected, "path: {}", path);
+        }
+
+        context.pop();
+        context.pop();
+        assert_eq!(
+            context.lookup("$.title").unwrap().and_then(Value::as_str),
+            Some("layout")
+        );
+        assert!(context.lookup("$foo.title").is_err());
+    }
+}
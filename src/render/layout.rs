@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use crate::io::{File, FileFormat, Value};
+
+/// Layout used for pages which do not declare a layout of their own.
+pub const DEFAULT_PAGE_LAYOUT: &str = "main.html";
+
+/// Special layout name to opt out of any layout, including the default one.
+pub const NO_LAYOUT: &str = "none";
+
+/// The layout name declared in the metadata (`layout: blog.html`) of the given file.
+pub fn declared(file: &File) -> Option<&str> {
+    file.meta()
+        .and_then(|meta| meta.value("layout"))
+        .and_then(Value::as_str)
+        .map(str::trim)
+}
+
+/// Turn a layout name (e.g. `docs/sidebar.html`) into the path (e.g. `docs.sidebar`)
+/// used to look it up within the workspace layouts, `None` in case no layout is desired.
+pub fn lookup_path(name: &str) -> Option<String> {
+    if name.is_empty() || name.eq_ignore_ascii_case(NO_LAYOUT) {
+        return None;
+    }
+    let path = Path::new(name);
+    let name = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.parse::<FileFormat>().is_ok() => path.with_extension(""),
+        _ => path.to_path_buf(),
+    };
+    let components: Vec<&str> = name
+        .components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .collect();
+    Some(components.join("."))
+}
@@ -0,0 +1,16 @@
+use pulldown_cmark::{html, Options, Parser};
+
+/// Convert CommonMark content into HTML,
+/// with the GFM extensions for tables, task lists, strikethrough and footnotes enabled.
+pub fn to_html(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_FOOTNOTES);
+
+    let parser = Parser::new_ext(markdown, options);
+    let mut output = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut output, parser);
+    output
+}
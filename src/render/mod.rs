@@ -0,0 +1,1103 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use crate::io::path::{PathComponent, PathIter};
+use crate::io::{File, FileFormat, FileOrValue, FileVariant, Meta, Value, VariantDimension, Workspace};
+use crate::publish::OutputMapper;
+
+mod context;
+pub use context::{MetaContext, MetaLayer};
+
+mod bash;
+pub use bash::{detect_shell, Shell};
+
+mod layout;
+mod markdown;
+mod script;
+
+/// Special include path referring to the content laid out by the current layout.
+pub const CONTENT_PATH: &str = "$%";
+
+/// Page metadata property defined by TSG, containing the URL of the page,
+/// unless the page defines this property itself.
+pub const PAGE_URL: &str = "url";
+
+/// Page metadata property defined by TSG, containing the locale the page is rendered in,
+/// or null when rendered without a locale, unless the page defines this property itself.
+///
+/// Likewise, a property named after each variant dimension declared by the site contains
+/// the value of that dimension the page is rendered in, or null.
+pub const PAGE_LOCALE: &str = "locale";
+
+/// Limits of the Rhai engine on all scripts, by name, where 0 means unlimited.
+pub fn script_limits() -> Vec<(&'static str, u64)> {
+    script::limits(&script::engine(Path::new(".")))
+}
+
+pub struct Renderer {
+    // shared with the scripts, which can look up files themselves
+    workspace: Rc<RefCell<Workspace>>,
+    engine: rhai::Engine,
+    mapper: OutputMapper,
+    context: MetaContext,
+    page: Option<File>,
+    contents: Vec<String>,
+    // workspace relative paths of the files currently being rendered, outer first
+    stack: Vec<String>,
+    // workspace relative paths of the include scripts already run for their data
+    // while rendering the current page
+    data_scripts: HashSet<String>,
+    // include paths of the scripts run for their data while rendering the current page
+    page_data_scripts: Vec<String>,
+    // data generated by Bash include scripts, by workspace relative path and the variant
+    // they were run for, reused for every page rendered in that variant
+    bash_data: HashMap<(String, FileVariant), Option<Meta>>,
+}
+
+/// A page rendered as HTML.
+pub struct RenderedPage {
+    /// Output path relative to the publish directory.
+    pub path: String,
+    pub url: String,
+    pub variant: FileVariant,
+    pub content: String,
+}
+
+enum Include {
+    File(Box<File>),
+    Value(Value),
+}
+
+impl Renderer {
+    pub fn new(workspace: Workspace) -> Renderer {
+        Renderer {
+            engine: script::engine(workspace.root()),
+            workspace: Rc::new(RefCell::new(workspace)),
+            mapper: OutputMapper::default(),
+            context: MetaContext::new(),
+            page: None,
+            contents: Vec::new(),
+            stack: Vec::new(),
+            data_scripts: HashSet::new(),
+            page_data_scripts: Vec::new(),
+            bash_data: HashMap::new(),
+        }
+    }
+
+    pub fn workspace(&self) -> Ref<'_, Workspace> {
+        self.workspace.borrow()
+    }
+
+    pub fn workspace_mut(&mut self) -> RefMut<'_, Workspace> {
+        self.workspace.borrow_mut()
+    }
+
+    pub fn output_mapper(&self) -> &OutputMapper {
+        &self.mapper
+    }
+
+    pub fn set_output_mapper(&mut self, mapper: OutputMapper) {
+        self.mapper = mapper;
+    }
+
+    /// Render all pages defined by the given page file in the given variant:
+    /// a single page for HTML and Markdown files, as well as for regular scripts,
+    /// or any amount of pages for scripts that define a `generate` function.
+    pub fn render_pages(
+        &mut self,
+        page: &File,
+        variant: &FileVariant,
+    ) -> Result<Vec<RenderedPage>> {
+        self.start_page(variant)?;
+        if let FileFormat::Rhai = page.info().format() {
+            let outer_page = self.page.replace(page.clone());
+            let result = self.generate_pages(page, variant);
+            self.page = outer_page;
+            if let Some(pages) = result? {
+                return Ok(pages);
+            }
+        }
+        let path = self.mapper.localized_page_path(page.info(), variant);
+        Ok(vec![RenderedPage {
+            url: self.mapper.url(&path),
+            path,
+            variant: variant.clone(),
+            content: self.render_localized_page(page, variant)?,
+        }])
+    }
+
+    /// Render the given page as HTML, laid out using the layout declared in its metadata,
+    /// or the default `main.html` layout in case it declared none and such a layout exists.
+    pub fn render_page(&mut self, page: &File) -> Result<String> {
+        self.render_localized_page(page, page.info().variant())
+    }
+
+    fn render_localized_page(&mut self, page: &File, variant: &FileVariant) -> Result<String> {
+        if self.page.is_none() {
+            self.start_page(variant)?;
+        }
+        let layouts = self.page_layouts(layout::declared(page))?;
+        let url = self.mapper.url(&self.mapper.localized_page_path(page.info(), variant));
+        let meta = with_page_property(meta_value(page), PAGE_URL, Value::String(url));
+        let meta = self.with_variant_meta(meta, variant);
+        let outer_page = self.page.replace(page.clone());
+        let result = self.with_layers(&layouts, MetaLayer::Page, meta, |renderer| {
+            renderer.render(page)
+        });
+        self.page = outer_page;
+        result
+    }
+
+    /// Render the content of the given file as HTML,
+    /// replacing all `<include>` tags with their rendered output.
+    pub fn render(&mut self, file: &File) -> Result<String> {
+        self.render_in(file, FileFormat::Html)
+    }
+
+    /// Render the content of the given file for the context of a parent with the given format.
+    ///
+    /// Markdown rendered within a Markdown parent is kept as (expanded) Markdown,
+    /// such that the parent converts it as a whole into HTML.
+    fn render_in(&mut self, file: &File, parent: FileFormat) -> Result<String> {
+        let path = self.relative_path(file);
+        if self.stack.contains(&path) {
+            let mut chain = self.stack.clone();
+            chain.push(path);
+            return Err(anyhow!("cyclic include: {}", chain.join(" -> ")));
+        }
+        self.stack.push(path);
+        let result = self.render_file(file, parent);
+        self.stack.pop();
+        result
+    }
+
+    fn render_file(&mut self, file: &File, parent: FileFormat) -> Result<String> {
+        match file.info().format() {
+            format @ (FileFormat::Html | FileFormat::Markdown) => {
+                let content = std::str::from_utf8(file.content())
+                    .with_context(|| format!("read content of {} as utf-8", file.info().path()))?;
+                let content = self.expand_includes(content, format)?;
+                Ok(match (format, parent) {
+                    (FileFormat::Markdown, FileFormat::Markdown) => content,
+                    (FileFormat::Markdown, _) => markdown::to_html(&content),
+                    _ => content,
+                })
+            }
+            FileFormat::Yaml | FileFormat::Json => Err(anyhow!(
+                "{}: data files cannot be rendered, include one of its properties instead",
+                file.info().path()
+            )),
+            FileFormat::Rhai => self.render_script(file, parent),
+            FileFormat::Bash => self.render_bash(file),
+        }
+    }
+
+    /// Expand all includes found in the content,
+    /// where the includes are rendered in the context of the given format.
+    fn expand_includes(&mut self, content: &str, format: FileFormat) -> Result<String> {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"(?s)<include>\s*(?P<path>.*?)\s*</include>").unwrap();
+        }
+        let mut output = String::with_capacity(content.len());
+        let mut last_end = 0;
+        for m in RE.captures_iter(content) {
+            let tag = m.get(0).unwrap();
+            output.push_str(&content[last_end..tag.start()]);
+            output.push_str(&self.render_include(&m["path"], format)?);
+            last_end = tag.end();
+        }
+        output.push_str(&content[last_end..]);
+        Ok(output)
+    }
+
+    fn render_include(&mut self, path: &str, parent: FileFormat) -> Result<String> {
+        if path == CONTENT_PATH {
+            return self
+                .contents
+                .last()
+                .cloned()
+                .ok_or_else(|| anyhow!("content '{}' can only be included by a layout", path));
+        }
+        if path.starts_with('$') {
+            return match self.context.lookup(path)? {
+                Some(value) => render_value(value),
+                None => Err(anyhow!("metadata '{}' not found", path)),
+            }
+            .with_context(|| format!("include '{}'", path));
+        }
+        match self.resolve_include(path)? {
+            Include::File(file) => self.render_include_file(&file, parent),
+            Include::Value(value) => render_value(&value),
+        }
+        .with_context(|| format!("include '{}'", path))
+    }
+
+    fn render_include_file(&mut self, file: &File, parent: FileFormat) -> Result<String> {
+        let layouts = match layout::declared(file) {
+            Some(name) => self.layout_chain(name)?,
+            None => Vec::new(),
+        };
+        self.render_laid_out(file, &layouts, MetaLayer::Include, parent)
+    }
+
+    /// Run the script and render the values and files it returned.
+    fn render_script(&mut self, file: &File, parent: FileFormat) -> Result<String> {
+        let outputs = script::eval(&self.engine, self.script_api(), file)
+            .with_context(|| format!("run script {}", self.relative_path(file)))?;
+        self.render_outputs(outputs, parent)
+    }
+
+    /// Run the Bash script with the metadata of the context and the includes
+    /// it requests as its environment, and return what it printed to STDOUT.
+    fn render_bash(&mut self, file: &File) -> Result<String> {
+        let mut env = bash::meta_env(&self.context);
+        for (name, path) in bash::include_paths(file) {
+            let value = match self.resolve_include(&path)? {
+                Include::Value(value) => render_value(&value),
+                Include::File(_) => Err(anyhow!(
+                    "only primitive values can be included by bash scripts"
+                )),
+            }
+            .with_context(|| format!("include '{}' for {}", path, self.relative_path(file)))?;
+            env.insert(name, value);
+        }
+        let root = self.workspace.borrow().root().to_path_buf();
+        bash::run(file, root, env)
+            .with_context(|| format!("run script {}", self.relative_path(file)))
+    }
+
+    /// Run the `generate` function of the page script, if defined,
+    /// and render all the pages it generated.
+    fn generate_pages(
+        &mut self,
+        page: &File,
+        variant: &FileVariant,
+    ) -> Result<Option<Vec<RenderedPage>>> {
+        let depth = self.context.len();
+        let meta = self.with_variant_meta(meta_value(page), variant);
+        self.context.push(MetaLayer::Page, meta);
+        let result = script::generate(&self.engine, self.script_api(), page, variant);
+        self.context.truncate(depth);
+        // errors are reported in the context of the page by the caller
+        let generated = match result? {
+            Some(generated) => generated,
+            None => return Ok(None),
+        };
+        let mut pages = Vec::with_capacity(generated.len());
+        for generated in generated {
+            let script::GeneratedPage {
+                path,
+                layout,
+                variant,
+                meta,
+                outputs,
+            } = generated;
+            self.start_page(&variant)?;
+            let layouts = self.page_layouts(layout.as_deref())?;
+            let url = self.mapper.url(&path);
+            let meta = with_page_property(meta, PAGE_URL, Value::String(url.clone()));
+            let meta = self.with_variant_meta(meta, &variant);
+            let content = self
+                .with_layers(&layouts, MetaLayer::Page, meta, |renderer| {
+                    renderer.render_outputs(outputs, FileFormat::Html)
+                })
+                .with_context(|| format!("render generated page '{}'", path))?;
+            pages.push(RenderedPage {
+                path,
+                url,
+                variant,
+                content,
+            });
+        }
+        Ok(Some(pages))
+    }
+
+    fn script_api(&self) -> script::Tsg {
+        script::Tsg::new(
+            self.workspace.clone(),
+            self.mapper,
+            self.context.clone(),
+            self.page.clone(),
+            self.workspace.borrow().variant().clone(),
+        )
+    }
+
+    fn render_outputs(
+        &mut self,
+        outputs: Vec<script::Output>,
+        parent: FileFormat,
+    ) -> Result<String> {
+        let mut content = String::new();
+        for output in outputs {
+            match output {
+                script::Output::Value(value) => content.push_str(&render_value(&value)?),
+                script::Output::File(file) => {
+                    content.push_str(&self.render_include_file(&file, parent)?)
+                }
+            }
+        }
+        Ok(content)
+    }
+
+    /// Render the file within its own metadata layer, and those of the layouts
+    /// it is laid out with, listed from the innermost to the outermost layout.
+    fn render_laid_out(
+        &mut self,
+        file: &File,
+        layouts: &[File],
+        layer: MetaLayer,
+        parent: FileFormat,
+    ) -> Result<String> {
+        self.with_layers(layouts, layer, meta_value(file), |renderer| {
+            // content of a file with a layout is always HTML, as that's what layouts are
+            if layouts.is_empty() {
+                renderer.render_in(file, parent)
+            } else {
+                renderer.render(file)
+            }
+        })
+    }
+
+    /// Render content within the metadata layers of the given layouts and
+    /// the given metadata, and lay out the rendered content using those layouts.
+    fn with_layers<F>(
+        &mut self,
+        layouts: &[File],
+        layer: MetaLayer,
+        meta: Value,
+        render: F,
+    ) -> Result<String>
+    where
+        F: FnOnce(&mut Renderer) -> Result<String>,
+    {
+        let depth = self.context.len();
+        for layout in layouts.iter().rev() {
+            self.context.push(MetaLayer::Layout, meta_value(layout));
+        }
+        self.context.push(layer, meta);
+        let result = render(self).and_then(|content| self.apply_layouts(layouts, content));
+        self.context.truncate(depth);
+        result
+    }
+
+    /// Lay out the content using the given layouts, from the innermost to the outermost layout.
+    fn apply_layouts(&mut self, layouts: &[File], mut content: String) -> Result<String> {
+        for layout in layouts {
+            self.contents.push(content);
+            let result = self.render(layout);
+            self.contents.pop();
+            content = result.with_context(|| format!("layout {}", self.relative_path(layout)))?;
+        }
+        Ok(content)
+    }
+
+    /// The layouts of a page declaring the given layout, if any,
+    /// using the default layout in case none is declared and it exists.
+    fn page_layouts(&mut self, declared: Option<&str>) -> Result<Vec<File>> {
+        match declared {
+            Some(name) => self.layout_chain(name),
+            None if self.has_layout(layout::DEFAULT_PAGE_LAYOUT)? => {
+                self.layout_chain(layout::DEFAULT_PAGE_LAYOUT)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Resolve the layout with the given name,
+    /// followed by the layout it declares in turn, if any.
+    fn layout_chain(&mut self, name: &str) -> Result<Vec<File>> {
+        let mut chain: Vec<File> = Vec::new();
+        let mut next = layout::lookup_path(name);
+        while let Some(path) = next {
+            let file = self.resolve_layout(&path)?;
+            if chain.iter().any(|l| l.info().path() == file.info().path()) {
+                let mut paths: Vec<String> = chain.iter().map(|l| self.relative_path(l)).collect();
+                paths.push(self.relative_path(&file));
+                return Err(anyhow!("cyclic layout: {}", paths.join(" -> ")));
+            }
+            next = layout::declared(&file).and_then(layout::lookup_path);
+            chain.push(file);
+        }
+        Ok(chain)
+    }
+
+    /// Path of the file relative to the workspace root, using `/` as separator.
+    fn relative_path(&self, file: &File) -> String {
+        let path = Path::new(file.info().path());
+        let root = self.workspace.borrow().root().to_path_buf();
+        let path = path.strip_prefix(&root).unwrap_or(path);
+        let components: Vec<_> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        components.join("/")
+    }
+
+    fn has_layout(&mut self, name: &str) -> Result<bool> {
+        match layout::lookup_path(name) {
+            Some(path) => Ok(self
+                .workspace
+                .borrow_mut()
+                .layout_or_value(path.as_str())?
+                .is_some()),
+            None => Ok(false),
+        }
+    }
+
+    fn resolve_layout(&mut self, path: &str) -> Result<File> {
+        match self.workspace.borrow_mut().layout_or_value(path)? {
+            Some(FileOrValue::File(file)) => Ok(file.clone()),
+            Some(FileOrValue::Value(_)) => Err(anyhow!("layout '{}' is not a file", path)),
+            None => Err(anyhow!("layout '{}' not found", path)),
+        }
+    }
+
+    fn resolve_include(&mut self, path: &str) -> Result<Include> {
+        if let Some(include) = self.lookup_include(path)? {
+            return Ok(include);
+        }
+        // the path might point into the data generated by a script,
+        // which is only run the first time its data is needed
+        if self.load_script_data(path)? {
+            if let Some(include) = self.lookup_include(path)? {
+                return Ok(include);
+            }
+        }
+        Err(anyhow!("include '{}' not found", path))
+    }
+
+    fn lookup_include(&mut self, path: &str) -> Result<Option<Include>> {
+        // clone the result so the workspace is free to be used
+        // again while rendering the included file
+        match self.workspace.borrow_mut().include_or_value(path)? {
+            Some(FileOrValue::File(file)) => Ok(Some(Include::File(Box::new(file.clone())))),
+            Some(FileOrValue::Value(value)) => Ok(Some(Include::Value(value.clone()))),
+            None => Ok(None),
+        }
+    }
+
+    /// Run the include script found at the longest prefix of the given path, if any
+    /// and if not run before for the current page, storing the data it generated as its
+    /// metadata: the JSON object printed by a Bash script, or the map (or YAML mapping)
+    /// returned by a Rhai script.
+    ///
+    /// Rhai scripts have access to the page being rendered, and are thus run for every page,
+    /// while the data of Bash scripts is reused for every page of the same variant.
+    ///
+    /// Returns true in case a script was run and generated data.
+    fn load_script_data(&mut self, path: &str) -> Result<bool> {
+        let names: Vec<&str> = PathIter::new(path)
+            .map_while(|component| match component {
+                PathComponent::Name(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        for len in (1..names.len()).rev() {
+            let prefix = names[..len].join(".");
+            let file = match self.lookup_include(&prefix)? {
+                Some(Include::File(file)) => file,
+                _ => continue,
+            };
+            let format = file.info().format();
+            if !matches!(format, FileFormat::Bash | FileFormat::Rhai) {
+                return Ok(false);
+            }
+            let script_path = self.relative_path(&file);
+            if !self.data_scripts.insert(script_path.clone()) {
+                return Ok(false);
+            }
+            self.page_data_scripts.push(prefix.clone());
+            let data = if let FileFormat::Rhai = format {
+                script::eval_data(&self.engine, self.script_api(), &file)
+                    .with_context(|| format!("run script {}", script_path))?
+                    .map(Meta::from)
+            } else {
+                let key = (script_path, self.workspace.borrow().variant().clone());
+                match self.bash_data.get(&key) {
+                    Some(data) => data.clone(),
+                    None => {
+                        let output = self.render_in(&file, FileFormat::Html)?;
+                        // output which isn't a JSON object can only be included as a whole
+                        let data = Meta::extract(FileFormat::Json, &mut output.into_bytes())
+                            .unwrap_or(None);
+                        self.bash_data.insert(key, data.clone());
+                        data
+                    }
+                }
+            };
+            if data.is_none() {
+                return Ok(false);
+            }
+            self.workspace
+                .borrow_mut()
+                .set_include_meta(prefix.as_str(), data)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Prepare rendering a new page in the given variant, so includes resolve to its variants.
+    fn start_page(&mut self, variant: &FileVariant) -> Result<()> {
+        self.reset_page_data()?;
+        self.workspace.borrow_mut().set_variant(variant.clone());
+        Ok(())
+    }
+
+    /// Page metadata with the locale of the page and its value of every declared
+    /// variant dimension defined, unless the page defined them already.
+    fn with_variant_meta(&self, meta: Value, variant: &FileVariant) -> Value {
+        let workspace = self.workspace.borrow();
+        with_variant_meta(meta, variant, workspace.config().dimensions())
+    }
+
+    /// Forget the data generated by include scripts for the previous page,
+    /// such that the next page uses the data of the scripts of its own variant.
+    fn reset_page_data(&mut self) -> Result<()> {
+        self.data_scripts.clear();
+        for include_path in self.page_data_scripts.drain(..) {
+            self.workspace
+                .borrow_mut()
+                .set_include_meta(include_path.as_str(), None)?;
+        }
+        Ok(())
+    }
+}
+
+fn meta_value(file: &File) -> Value {
+    file.meta()
+        .map(|meta| meta.as_value().clone())
+        .unwrap_or(Value::Null)
+}
+
+fn with_variant_meta(meta: Value, variant: &FileVariant, dimensions: &[VariantDimension]) -> Value {
+    let locale = variant.locale().map(|locale| Value::from(locale.as_str()));
+    let mut meta = with_page_property(meta, PAGE_LOCALE, locale.unwrap_or(Value::Null));
+    for dimension in dimensions {
+        let value = variant.value(&dimension.name).map(Value::from);
+        meta = with_page_property(meta, &dimension.name, value.unwrap_or(Value::Null));
+    }
+    meta
+}
+
+fn with_page_property(meta: Value, name: &str, value: Value) -> Value {
+    match meta {
+        Value::Null => Value::Mapping([(name.to_owned(), value)].into()),
+        Value::Mapping(mut map) => {
+            map.entry(name.to_owned()).or_insert(value);
+            Value::Mapping(map)
+        }
+        meta => meta,
+    }
+}
+
+/// Render a primitive value as a string,
+/// sequences and mappings cannot be rendered directly.
+pub fn render_value(value: &Value) -> Result<String> {
+    value
+        .to_text()
+        .ok_or_else(|| anyhow!("only primitive values can be rendered"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::publish::{LocaleStyle, PathStyle};
+    use crate::testing::workspace;
+
+    use std::path::Path;
+
+    fn render_page(root: &Path, page: &str) -> Result<String> {
+        let file = File::read(root, root.join("pages").join(page))?;
+        let mut renderer = Renderer::new(Workspace::read(root)?);
+        renderer.render_page(&file)
+    }
+
+    #[test]
+    fn test_render_includes() {
+        let root = workspace(
+            "includes",
+            &[
+                (
+                    "pages/index.html",
+                    "<h1><include>strings.title</include></h1>\n<include> intro </include>",
+                ),
+                ("includes/strings.yml", "title: Hello\nyear: 2021\n"),
+                (
+                    "includes/intro.html",
+                    "<p><include>strings.year</include></p>",
+                ),
+            ],
+        );
+        assert_eq!(
+            render_page(&root, "index.html").unwrap(),
+            "<h1>Hello</h1>\n<p>2021</p>"
+        );
+    }
+
+    #[test]
+    fn test_render_typed_values() {
+        let root = workspace(
+            "typed",
+            &[
+                (
+                    "pages/index.md",
+                    "---\nid: 9007199254740993\nprice: 2.0\nratio: 0.25\ndate: 2021-02-01\nupdated: 2021-02-01 10:30:00 +2\n---\n<include>$.id</include>|<include>$.price</include>|<include>$.ratio</include>|<include>$.date</include>|<include>$.updated</include>",
+                ),
+            ],
+        );
+        assert_eq!(
+            render_page(&root, "index.md").unwrap(),
+            "<p>9007199254740993|2.0|0.25|2021-02-01|2021-02-01T10:30:00+02:00</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_render_include_not_found() {
+        let root = workspace(
+            "not-found",
+            &[("pages/index.html", "<include>foo.bar</include>")],
+        );
+        let err = render_page(&root, "index.html").unwrap_err();
+        assert!(format!("{:#}", err).contains("include 'foo.bar' not found"));
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let root = workspace(
+            "markdown",
+            &[
+                (
+                    "pages/index.md",
+                    "# <include>strings.title</include>\n\n<include>intro</include>\n",
+                ),
+                ("pages/about.html", "<div><include>intro</include></div>"),
+                ("includes/strings.yml", "title: Hello *World*\n"),
+                ("includes/intro.md", "- [x] ~~done~~\n"),
+            ],
+        );
+        assert_eq!(
+            render_page(&root, "index.md").unwrap(),
+            "<h1>Hello <em>World</em></h1>\n<ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n<del>done</del></li>\n</ul>\n"
+        );
+        assert_eq!(
+            render_page(&root, "about.html").unwrap(),
+            "<div><ul>\n<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n<del>done</del></li>\n</ul>\n</div>"
+        );
+    }
+
+    #[test]
+    fn test_render_layouts() {
+        let root = workspace(
+            "layouts",
+            &[
+                ("pages/index.html", "<p>index</p>"),
+                ("pages/post.md", "---\nlayout: blog.html\n---\n# Post\n"),
+                ("pages/raw.html", "<!--\nlayout: none\n-->\n<p>raw</p>"),
+                ("pages/card.html", "<include>card</include>"),
+                ("layouts/main.html", "<main><include>$%</include></main>"),
+                (
+                    "layouts/blog.html",
+                    "<!--\nlayout: main\n-->\n<article><include>$%</include></article>",
+                ),
+                (
+                    "layouts/card.html",
+                    "<div class=\"card\"><include>$%</include></div>",
+                ),
+                ("includes/card.md", "---\nlayout: card.html\n---\n*card*"),
+            ],
+        );
+        assert_eq!(
+            render_page(&root, "index.html").unwrap(),
+            "<main><p>index</p></main>"
+        );
+        assert_eq!(
+            render_page(&root, "post.md").unwrap(),
+            "<main><article><h1>Post</h1>\n</article></main>"
+        );
+        assert_eq!(render_page(&root, "raw.html").unwrap(), "<p>raw</p>");
+        assert_eq!(
+            render_page(&root, "card.html").unwrap(),
+            "<main><div class=\"card\"><p><em>card</em></p>\n</div></main>"
+        );
+    }
+
+    #[test]
+    fn test_render_content_outside_layout() {
+        let root = workspace("content", &[("pages/index.html", "<include>$%</include>")]);
+        let err = render_page(&root, "index.html").unwrap_err();
+        assert!(format!("{:#}", err).contains("can only be included by a layout"));
+    }
+
+    #[test]
+    fn test_render_cyclic_include() {
+        let root = workspace(
+            "cyclic",
+            &[
+                ("pages/index.md", "<include>a</include>"),
+                ("includes/a.md", "<include>b</include>"),
+                ("includes/b.html", "<include>a</include>"),
+            ],
+        );
+        let err = render_page(&root, "index.md").unwrap_err();
+        assert!(format!("{:#}", err).contains(
+            "cyclic include: pages/index.md -> includes/a.md -> includes/b.html -> includes/a.md"
+        ));
+    }
+
+    #[test]
+    fn test_render_meta() {
+        let root = workspace(
+            "meta",
+            &[
+                ("pages/index.md", "---\ntitle: Index\n---\n<include>card</include>"),
+                (
+                    "layouts/main.html",
+                    "<!--\ntitle: Site\nsite: TSG\n-->\n<title><include>$.title</include> - <include>$layout.title</include></title><include>$%</include>",
+                ),
+                (
+                    "includes/card.html",
+                    "<!--\ntitle: Card\n-->\n<p><include>$.title</include>|<include>$page.title</include>|<include>$.site</include></p>",
+                ),
+            ],
+        );
+        assert_eq!(
+            render_page(&root, "index.md").unwrap(),
+            "<title>Index - Site</title><p>Card|Index|TSG</p>"
+        );
+    }
+
+    #[test]
+    fn test_render_script() {
+        let root = workspace(
+            "script",
+            &[
+                (
+                    "pages/index.md",
+                    "---\ntitle: Index\n---\n<include>list</include>",
+                ),
+                (
+                    "pages/about.rhai",
+                    "`<p>${tsg.pages(\"index.title\")} ${tsg.pages().type}</p>`",
+                ),
+                (
+                    "includes/list.rhai",
+                    r#"
+                    let card = tsg.includes("card");
+                    card.set_meta("title", tsg.meta("title") + "!");
+                    let posts = tsg.includes("posts.*");
+                    [card, " ", posts.len(), " ", tsg.includes("posts.hello.title"), " ", card.type]
+                    "#,
+                ),
+                (
+                    "includes/card.md",
+                    "---\ntitle: Card\n---\n*<include>$.title</include>*",
+                ),
+                ("includes/posts/hello.md", "---\ntitle: Hello\n---\n"),
+                ("includes/posts/world.md", "---\ntitle: World\n---\n"),
+            ],
+        );
+        assert_eq!(
+            render_page(&root, "index.md").unwrap(),
+            "<p><em>Index!</em> 2 Hello md</p>\n"
+        );
+        assert_eq!(
+            render_page(&root, "about.rhai").unwrap(),
+            "<p>Index rhai</p>"
+        );
+    }
+
+    #[test]
+    fn test_render_bash() {
+        let root = workspace(
+            "bash",
+            &[
+                (
+                    "pages/index.md",
+                    "---\ntitle: Index\n---\n<include>greeting</include>",
+                ),
+                ("pages/fail.html", "<include>fail</include>"),
+                (
+                    "includes/greeting.sh",
+                    "# prints $TSG_INCLUDE_STRINGS_HELLO, not $TSG_INCLUDE_MISSING\necho \"$TSG_META_TITLE: $TSG_INCLUDE_STRINGS_HELLO ${HOME:-nohome}\" # or TSG_INCLUDE_NONE\necho \"$TSG_INCLUDE_STRINGS_GOOD__BYE#\"\necho \"issue #1 by $TSG_INCLUDE_STRINGS_SITE_NAME\" '#2' # $TSG_INCLUDE_NONE\n",
+                ),
+                ("includes/strings.yml", "hello: Hello\ngood_bye: Bye\nsite:\n  name: Example\n"),
+                ("includes/fail.sh", "echo oops >&2\nexit 3\n"),
+            ],
+        );
+        assert_eq!(
+            render_page(&root, "index.md").unwrap(),
+            "<p>Index: Hello nohome\nBye#\nissue #1 by Example #2</p>\n"
+        );
+        let err = format!("{:#}", render_page(&root, "fail.html").unwrap_err());
+        assert!(err.contains("run script includes/fail.sh"), "{}", err);
+        assert!(err.contains("oops"), "{}", err);
+    }
+
+    #[test]
+    fn test_render_bash_data() {
+        let root = workspace(
+            "bash-data",
+            &[
+                (
+                    "pages/index.html",
+                    "<include>data.author.name</include> (<include>data.year</include>)",
+                ),
+                (
+                    "includes/data.sh",
+                    "echo '{\"author\": {\"name\": \"Glen\"}, \"year\": 2021}'\n",
+                ),
+            ],
+        );
+        assert_eq!(render_page(&root, "index.html").unwrap(), "Glen (2021)");
+    }
+
+    #[test]
+    fn test_render_bash_data_variants() {
+        let root = workspace(
+            "bash-data-variants",
+            &[
+                ("pages/index.html", "<include>data.greeting</include> <include>data.locale</include>"),
+                (
+                    "includes/data.sh",
+                    "echo \"{\\\"greeting\\\": \\\"hello\\\", \\\"locale\\\": \\\"$TSG_META_LOCALE\\\"}\"\n",
+                ),
+                (
+                    "includes/data.nl.sh",
+                    "echo \"{\\\"greeting\\\": \\\"hallo\\\", \\\"locale\\\": \\\"$TSG_META_LOCALE\\\"}\"\n",
+                ),
+            ],
+        );
+        let mut renderer = Renderer::new(Workspace::read(&root).unwrap());
+        let index = File::read(&root, root.join("pages/index.html")).unwrap();
+        let test_cases = [
+            (None, "hello "),
+            (Some("nl"), "hallo nl"),
+            (Some("nl.be"), "hallo nl.be"),
+            (None, "hello "),
+        ];
+        for (locale, expected) in test_cases {
+            let variant = FileVariant::from(locale.map(|l| l.parse().unwrap()));
+            let pages = renderer.render_pages(&index, &variant).unwrap();
+            assert_eq!(pages[0].content, expected, "locale: {:?}", locale);
+        }
+    }
+
+    #[test]
+    fn test_render_rhai_data() {
+        let root = workspace(
+            "rhai-data",
+            &[
+                (
+                    "pages/index.html",
+                    "<!--\ntitle: Index\n-->\n<include>data.page</include> <include>yaml.list.1</include>",
+                ),
+                (
+                    "pages/about.html",
+                    "<!--\ntitle: About\n-->\n<include>data.page</include>",
+                ),
+                ("includes/data.rhai", "#{ page: tsg.pages().meta(\"title\") }"),
+                ("includes/yaml.rhai", "\"list: [a, b]\""),
+            ],
+        );
+        let mut renderer = Renderer::new(Workspace::read(&root).unwrap());
+        for (page, expected) in [("index.html", "Index b"), ("about.html", "About")] {
+            let file = File::read(&root, root.join("pages").join(page)).unwrap();
+            assert_eq!(renderer.render_page(&file).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_render_generated_pages() {
+        let root = workspace(
+            "generator",
+            &[
+                (
+                    "pages/blog.rhai",
+                    r#"
+                    const PER_PAGE = 2;
+
+                    fn generate(generator) {
+                        let posts = tsg.includes("posts.*");
+                        posts.sort(|a, b| if a.name < b.name { -1 } else { 1 });
+                        let url = ();
+                        for (post, index) in posts {
+                            url = generator.html(`blog/${post.name}.html`, post, #{
+                                layout: "post.html",
+                                locale: "nl",
+                                meta: #{ index: index + 1 },
+                            });
+                        }
+                        generator.html("blog/index.html", `<p>${posts.len()}/${global::PER_PAGE} ${url}</p>`);
+                    }
+                    "#,
+                ),
+                ("layouts/main.html", "<main><include>$%</include></main>"),
+                (
+                    "layouts/post.html",
+                    "<article id=\"<include>$.index</include>\"><include>$%</include></article>",
+                ),
+                ("includes/posts/a.md", "# A"),
+                ("includes/posts/b.md", "# B"),
+            ],
+        );
+        let file = File::read(&root, root.join("pages/blog.rhai")).unwrap();
+        let mut renderer = Renderer::new(Workspace::read(&root).unwrap());
+        let mut render = |locale: Option<&str>| -> Vec<_> {
+            let variant = FileVariant::from(locale.map(|l| l.parse().unwrap()));
+            renderer
+                .render_pages(&file, &variant)
+                .unwrap()
+                .into_iter()
+                .map(|page| {
+                    (
+                        page.path,
+                        page.variant.locale().map(|locale| locale.as_str().to_owned()),
+                        page.content,
+                    )
+                })
+                .collect()
+        };
+        // pages of another locale are only emitted when rendering in that locale
+        assert_eq!(
+            render(None),
+            vec![(
+                "blog/index.html".to_owned(),
+                None,
+                "<main><p>2/2 /nl/blog/b.html</p></main>".to_owned()
+            )]
+        );
+        assert_eq!(
+            render(Some("nl")),
+            vec![
+                (
+                    "nl/blog/a.html".to_owned(),
+                    Some("nl".to_owned()),
+                    "<article id=\"1\"><h1>A</h1>\n</article>".to_owned()
+                ),
+                (
+                    "nl/blog/b.html".to_owned(),
+                    Some("nl".to_owned()),
+                    "<article id=\"2\"><h1>B</h1>\n</article>".to_owned()
+                ),
+                (
+                    "nl/blog/index.html".to_owned(),
+                    Some("nl".to_owned()),
+                    "<main><p>2/2 /nl/blog/b.html</p></main>".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_page_urls() {
+        let root = workspace(
+            "urls",
+            &[
+                ("pages/foo/bar.md", "<include>$page.url</include>"),
+                (
+                    "pages/custom.html",
+                    "<!--\nurl: /elsewhere\n-->\n<include>$.url</include>",
+                ),
+                (
+                    "pages/index.nl.rhai",
+                    "`${tsg.pages().url} ${tsg.pages(\"foo.bar\").url}`",
+                ),
+            ],
+        );
+        let mut renderer = Renderer::new(Workspace::read(&root).unwrap());
+        renderer.set_output_mapper(OutputMapper::new(PathStyle::Pretty, LocaleStyle::Directory));
+        let test_cases = vec![
+            ("foo/bar.md", "foo/bar/index.html", "<p>/foo/bar/</p>\n"),
+            ("custom.html", "custom/index.html", "/elsewhere"),
+            ("index.nl.rhai", "nl/index.html", "/nl/ /nl/foo/bar/"),
+        ];
+        for (page, expected_path, expected_content) in test_cases {
+            let file = File::read(&root, root.join("pages").join(page)).unwrap();
+            let pages = renderer.render_pages(&file, file.info().variant()).unwrap();
+            assert_eq!(pages.len(), 1);
+            assert_eq!(pages[0].path, expected_path);
+            assert_eq!(pages[0].content, expected_content);
+        }
+    }
+
+    #[test]
+    fn test_render_locale() {
+        let root = workspace(
+            "locale",
+            &[
+                (
+                    "pages/index.html",
+                    "<include>$.locale</include> <include>greeting</include> <include>who</include>",
+                ),
+                ("pages/gen.rhai", "fn generate(generator) { generator.html(\"gen.html\", generator.locale) }"),
+                ("includes/greeting.html", "Hello"),
+                ("includes/greeting.nl.html", "Hallo"),
+                ("includes/who.rhai", "tsg.meta(\"locale\")"),
+                ("includes/who.nl.be.sh", "echo \"$TSG_META_LOCALE\"\n"),
+            ],
+        );
+        let mut renderer = Renderer::new(Workspace::read(&root).unwrap());
+        let index = File::read(&root, root.join("pages/index.html")).unwrap();
+        let generator = File::read(&root, root.join("pages/gen.rhai")).unwrap();
+        let test_cases = vec![
+            (None, "index.html", " Hello ", "gen.html", ""),
+            (Some("nl"), "nl/index.html", "nl Hallo nl", "nl/gen.html", "nl"),
+            (Some("nl.be"), "nl.be/index.html", "nl.be Hallo nl.be", "nl.be/gen.html", "nl.be"),
+        ];
+        for (locale, index_path, index_content, gen_path, gen_content) in test_cases {
+            let variant = FileVariant::from(locale.map(|l| l.parse().unwrap()));
+            let pages = renderer.render_pages(&index, &variant).unwrap();
+            assert_eq!(pages[0].path, index_path);
+            assert_eq!(pages[0].content, index_content);
+            let pages = renderer.render_pages(&generator, &variant).unwrap();
+            assert_eq!(pages[0].path, gen_path);
+            assert_eq!(pages[0].variant, variant);
+            assert_eq!(pages[0].content, gen_content);
+        }
+    }
+
+    #[test]
+    fn test_render_variant() {
+        let root = workspace(
+            "variant",
+            &[
+                ("tsg.yml", "variants:\n  theme: [dark]\n"),
+                (
+                    "pages/index.html",
+                    "<include>$.theme</include> <include>greeting</include> <include>style</include>",
+                ),
+                ("pages/gen.rhai", "fn generate(generator) { generator.html(\"gen.html\", generator.variant) }"),
+                ("includes/greeting.html", "Hello"),
+                ("includes/greeting.nl.html", "Hallo"),
+                ("includes/style.html", "light"),
+                ("includes/style.dark.html", "dark"),
+                ("includes/style.nl.html", "licht"),
+            ],
+        );
+        let mut renderer = Renderer::new(Workspace::read(&root).unwrap());
+        let config = renderer.workspace().config().clone();
+        let index = File::read(&root, root.join("pages/index.html")).unwrap();
+        let generator = File::read(&root, root.join("pages/gen.rhai")).unwrap();
+        let test_cases = vec![
+            ("", "index.html", " Hello light", "gen.html"),
+            ("dark", "dark/index.html", "dark Hello dark", "dark/gen.html"),
+            ("nl", "nl/index.html", " Hallo licht", "nl/gen.html"),
+            ("nl.dark", "nl/dark/index.html", "dark Hallo licht", "nl/dark/gen.html"),
+        ];
+        for (variant, index_path, index_content, gen_path) in test_cases {
+            let variant = FileVariant::parse(variant, &config).unwrap();
+            let pages = renderer.render_pages(&index, &variant).unwrap();
+            assert_eq!(pages[0].path, index_path);
+            assert_eq!(pages[0].content, index_content);
+            let pages = renderer.render_pages(&generator, &variant).unwrap();
+            assert_eq!(pages[0].path, gen_path);
+            assert_eq!(pages[0].content, variant.to_string());
+        }
+    }
+}
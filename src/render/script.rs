@@ -0,0 +1,550 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use indexmap::IndexMap;
+use rhai::module_resolvers::FileModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, FLOAT, INT};
+
+use super::MetaContext;
+use crate::io::path::{PathComponent, PathIter};
+use crate::io::yaml;
+use crate::io::{File, FileKind, FileOrValue, FileVariant, Meta, Value, Workspace};
+use crate::publish::OutputMapper;
+
+/// A single rendered unit returned by a script.
+pub enum Output {
+    Value(Value),
+    File(Box<File>),
+}
+
+/// The `tsg` object in scope of every script.
+#[derive(Clone)]
+pub struct Tsg {
+    workspace: Rc<RefCell<Workspace>>,
+    mapper: OutputMapper,
+    context: MetaContext,
+    page: Option<File>,
+    // variant of the page being rendered, which the pages found are published in as well
+    variant: FileVariant,
+}
+
+/// The `File` type as exposed to scripts,
+/// where its metadata is an in-memory copy that can be modified by the script.
+#[derive(Clone)]
+pub struct ScriptFile {
+    file: File,
+    meta: Value,
+    mapper: OutputMapper,
+    // variant of the page being rendered, used for the URL of pages
+    variant: FileVariant,
+}
+
+/// The `generator` passed to the `generate` function of page scripts,
+/// collecting the pages emitted by the script.
+#[derive(Clone)]
+pub struct Generator {
+    page: File,
+    // variant the page is rendered in, and thus the default variant of the pages it emits
+    variant: FileVariant,
+    mapper: OutputMapper,
+    pages: Rc<RefCell<Vec<GeneratedPage>>>,
+}
+
+/// A page emitted by a page script.
+pub struct GeneratedPage {
+    /// Output path relative to the publish directory.
+    pub path: String,
+    pub layout: Option<String>,
+    pub variant: FileVariant,
+    pub meta: Value,
+    pub outputs: Vec<Output>,
+}
+
+/// Name of the function which turns a page script into a generator of pages.
+pub const GENERATE_FN: &str = "generate";
+
+/// Maximum nesting depth of expressions at the global level of a script.
+pub const MAX_EXPR_DEPTH: usize = 64;
+/// Maximum nesting depth of expressions within functions of a script.
+pub const MAX_FUNCTION_EXPR_DEPTH: usize = 64;
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// Limits of the engine on the scripts it runs, by name, where 0 means unlimited.
+pub fn limits(engine: &Engine) -> Vec<(&'static str, u64)> {
+    // some limits are disabled by setting them to their maximum rather than 0
+    let limit = |n: usize| if n == usize::MAX { 0 } else { n as u64 };
+    vec![
+        ("max expression depth", limit(engine.max_expr_depth())),
+        ("max function expression depth", limit(engine.max_function_expr_depth())),
+        ("max call levels", limit(engine.max_call_levels())),
+        ("max operations", engine.max_operations()),
+        ("max modules", limit(engine.max_modules())),
+        ("max variables", limit(engine.max_variables())),
+        ("max functions", limit(engine.max_functions())),
+        ("max string size", limit(engine.max_string_size())),
+        ("max array size", limit(engine.max_array_size())),
+        ("max map size", limit(engine.max_map_size())),
+    ]
+}
+
+/// Create the engine used to run all scripts of the given workspace,
+/// where modules are imported relative to its `includes` directory.
+pub fn engine(root: &Path) -> Engine {
+    let mut engine = Engine::new();
+    // explicit limits, as the defaults differ between debug and release builds,
+    // and generator scripts tend to nest option maps within loops within functions
+    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_FUNCTION_EXPR_DEPTH);
+    engine.set_module_resolver(FileModuleResolver::new_with_path(root.join("includes")));
+
+    engine
+        .register_type_with_name::<Tsg>("Tsg")
+        .register_fn("includes", Tsg::includes)
+        .register_fn("pages", Tsg::page)
+        .register_fn("pages", Tsg::pages)
+        .register_fn("meta", Tsg::meta);
+
+    engine
+        .register_type_with_name::<ScriptFile>("File")
+        .register_fn("meta", ScriptFile::meta)
+        .register_fn("set_meta", ScriptFile::set_meta)
+        .register_get("content", ScriptFile::content)
+        .register_get("name", ScriptFile::name)
+        .register_get("path", ScriptFile::path)
+        .register_get("locale", ScriptFile::locale)
+        .register_get("variant", ScriptFile::variant)
+        .register_get("url", ScriptFile::url)
+        .register_get("type", ScriptFile::file_type);
+
+    engine
+        .register_type_with_name::<Generator>("Generator")
+        .register_get("page", Generator::page)
+        .register_get("locale", Generator::locale)
+        .register_get("variant", Generator::variant)
+        .register_fn("html", Generator::html)
+        .register_fn("html", Generator::html_with_options);
+
+    engine
+}
+
+impl Tsg {
+    pub fn new(
+        workspace: Rc<RefCell<Workspace>>,
+        mapper: OutputMapper,
+        context: MetaContext,
+        page: Option<File>,
+        variant: FileVariant,
+    ) -> Tsg {
+        Tsg {
+            workspace,
+            mapper,
+            context,
+            page,
+            variant,
+        }
+    }
+
+    fn includes(&mut self, path: &str) -> ScriptResult<Dynamic> {
+        let mut workspace = self.workspace.borrow_mut();
+        let results = workspace
+            .include_or_value_iter(path)
+            .map(|result| result.map(|result| to_dynamic(result, self.mapper, &self.variant)));
+        collect_results(path, results)
+    }
+
+    fn page(&mut self) -> Dynamic {
+        match &self.page {
+            Some(page) => Dynamic::from(ScriptFile::new(
+                page.clone(),
+                self.mapper,
+                self.variant.clone(),
+            )),
+            None => Dynamic::UNIT,
+        }
+    }
+
+    fn pages(&mut self, path: &str) -> ScriptResult<Dynamic> {
+        let mut workspace = self.workspace.borrow_mut();
+        let results = workspace
+            .page_or_value_iter(path)
+            .map(|result| result.map(|result| to_dynamic(result, self.mapper, &self.variant)));
+        collect_results(path, results)
+    }
+
+    fn meta(&mut self, path: &str) -> ScriptResult<Dynamic> {
+        match self.context.lookup(path) {
+            Ok(value) => Ok(value.map(value_to_dynamic).unwrap_or(Dynamic::UNIT)),
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+}
+
+impl ScriptFile {
+    pub fn new(file: File, mapper: OutputMapper, variant: FileVariant) -> ScriptFile {
+        let meta = file
+            .meta()
+            .map(|meta| meta.as_value().clone())
+            .unwrap_or(Value::Null);
+        ScriptFile {
+            file,
+            meta,
+            mapper,
+            variant,
+        }
+    }
+
+    /// The file with its metadata replaced by the (modified) in-memory copy.
+    pub fn into_file(self) -> File {
+        let mut file = self.file;
+        file.set_meta(match self.meta {
+            Value::Null => None,
+            value => Some(Meta::from(value)),
+        });
+        file
+    }
+
+    fn meta(&mut self, path: &str) -> Dynamic {
+        self.meta
+            .value(path)
+            .map(value_to_dynamic)
+            .unwrap_or(Dynamic::UNIT)
+    }
+
+    fn set_meta(&mut self, path: &str, value: Dynamic) -> ScriptResult<()> {
+        let value = dynamic_to_value(value).map_err(|err| err.to_string())?;
+        set_value(&mut self.meta, path, value).map_err(|err| err.to_string().into())
+    }
+
+    fn content(&mut self) -> String {
+        String::from_utf8_lossy(self.file.content()).into_owned()
+    }
+
+    fn name(&mut self) -> String {
+        self.file.info().name().to_owned()
+    }
+
+    fn path(&mut self) -> String {
+        self.file.info().path().to_owned()
+    }
+
+    fn locale(&mut self) -> String {
+        self.file
+            .info()
+            .locale()
+            .map(|locale| locale.as_str().to_owned())
+            .unwrap_or_default()
+    }
+
+    fn variant(&mut self) -> String {
+        self.file.info().variant().to_string()
+    }
+
+    /// URL the file is published at when rendered in the variant of the current page,
+    /// only defined for pages.
+    fn url(&mut self) -> String {
+        match self.file.info().kind() {
+            FileKind::Page => self
+                .mapper
+                .url(&self.mapper.localized_page_path(self.file.info(), &self.variant)),
+            _ => String::new(),
+        }
+    }
+
+    fn file_type(&mut self) -> String {
+        Path::new(self.file.info().path())
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    }
+}
+
+impl Generator {
+    fn page(&mut self) -> ScriptFile {
+        ScriptFile::new(self.page.clone(), self.mapper, self.variant.clone())
+    }
+
+    fn locale(&mut self) -> String {
+        self.variant
+            .locale()
+            .map(|locale| locale.as_str().to_owned())
+            .unwrap_or_default()
+    }
+
+    fn variant(&mut self) -> String {
+        self.variant.to_string()
+    }
+
+    fn html(&mut self, path: &str, content: Dynamic) -> ScriptResult<String> {
+        self.html_with_options(path, content, Map::new())
+    }
+
+    /// Emit a page at the given output path, where the options can define
+    /// the `layout`, `locale` and `meta` (mapping) of the page.
+    /// The page is emitted in the variant the page script is rendered in, unless the
+    /// `locale` option differs from its locale: as the script is run for every locale,
+    /// such a page is only emitted when rendered in that locale.
+    ///
+    /// Returns the URL of the page, also when not emitted by this run.
+    fn html_with_options(
+        &mut self,
+        path: &str,
+        content: Dynamic,
+        options: Map,
+    ) -> ScriptResult<String> {
+        let page = GeneratedPage::new(path, content, options, &self.variant, &self.mapper)
+            .map_err(|err| err.to_string())?;
+        let url = self.mapper.url(&page.path);
+        if page.variant == self.variant {
+            self.pages.borrow_mut().push(page);
+        }
+        Ok(url)
+    }
+}
+
+impl GeneratedPage {
+    fn new(
+        path: &str,
+        content: Dynamic,
+        mut options: Map,
+        variant: &FileVariant,
+        mapper: &OutputMapper,
+    ) -> Result<GeneratedPage> {
+        let path = path.trim().trim_start_matches('/');
+        if path.is_empty() || path.split(['/', '\\']).any(|c| c == "..") {
+            return Err(anyhow!("invalid page output path '{}'", path));
+        }
+        let layout = string_option(&mut options, "layout")?;
+        let variant = match string_option(&mut options, "locale")? {
+            Some(locale) => FileVariant::new(Some(locale.parse()?), variant.values().to_vec()),
+            None => variant.clone(),
+        };
+        let meta = match options.remove("meta").map(dynamic_to_value).transpose()? {
+            None | Some(Value::Null) => Value::Null,
+            Some(meta @ Value::Mapping(_)) => meta,
+            Some(_) => return Err(anyhow!("page option 'meta' has to be a mapping")),
+        };
+        if let Some(name) = options.keys().next() {
+            return Err(anyhow!("unknown page option '{}'", name));
+        }
+        let mut outputs = Vec::new();
+        collect_outputs(content, &mut outputs)?;
+        Ok(GeneratedPage {
+            path: mapper.localized_path(path, &variant),
+            layout,
+            variant,
+            meta,
+            outputs,
+        })
+    }
+}
+
+fn string_option(options: &mut Map, name: &str) -> Result<Option<String>> {
+    match options.remove(name) {
+        None => Ok(None),
+        Some(value) if value.is_string() => Ok(value.into_string().ok()),
+        Some(_) => Err(anyhow!("page option '{}' has to be a string", name)),
+    }
+}
+
+/// Run the `generate` function of the given page script, if it defines one,
+/// returning the pages it generated when rendered in the given variant.
+pub fn generate(
+    engine: &Engine,
+    tsg: Tsg,
+    file: &File,
+    variant: &FileVariant,
+) -> Result<Option<Vec<GeneratedPage>>> {
+    let mapper = tsg.mapper;
+    let source = std::str::from_utf8(file.content())?;
+    let ast = engine.compile(source).map_err(|err| anyhow!("{}", err))?;
+    if !ast
+        .iter_functions()
+        .any(|f| f.name == GENERATE_FN && f.params.len() == 1)
+    {
+        return Ok(None);
+    }
+    let generator = Generator {
+        page: file.clone(),
+        variant: variant.clone(),
+        mapper,
+        pages: Rc::new(RefCell::new(Vec::new())),
+    };
+    let mut scope = Scope::new();
+    scope.push_constant("tsg", tsg);
+    engine
+        .call_fn::<Dynamic>(&mut scope, &ast, GENERATE_FN, (generator.clone(),))
+        .map(|_| ())
+        .map_err(|err| anyhow!("{}", err))?;
+    Ok(Some(generator.pages.take()))
+}
+
+/// Run the given script, returning the values it produced to be rendered.
+pub fn eval(engine: &Engine, tsg: Tsg, file: &File) -> Result<Vec<Output>> {
+    let result = eval_dynamic(engine, tsg, file)?;
+    let mut outputs = Vec::new();
+    collect_outputs(result, &mut outputs)?;
+    Ok(outputs)
+}
+
+/// Run the script for the data it returns, being either a map
+/// or a string containing a YAML mapping. Any other result is not data.
+pub fn eval_data(engine: &Engine, tsg: Tsg, file: &File) -> Result<Option<Value>> {
+    let result = eval_dynamic(engine, tsg, file)?;
+    if result.is_map() {
+        return dynamic_to_value(result).map(Some);
+    }
+    if result.is_string() {
+        let yaml = result.into_string().unwrap();
+        return Ok(match yaml::parse(&yaml) {
+            Ok(value @ Value::Mapping(_)) => Some(value),
+            _ => None,
+        });
+    }
+    Ok(None)
+}
+
+fn eval_dynamic(engine: &Engine, tsg: Tsg, file: &File) -> Result<Dynamic> {
+    let source = std::str::from_utf8(file.content())?;
+    let mut scope = Scope::new();
+    scope.push_constant("tsg", tsg);
+    engine
+        .eval_with_scope::<Dynamic>(&mut scope, source)
+        .map_err(|err| anyhow!("{}", err))
+}
+
+fn collect_outputs(result: Dynamic, outputs: &mut Vec<Output>) -> Result<()> {
+    if result.is_array() {
+        for item in result.cast::<Array>() {
+            collect_outputs(item, outputs)?;
+        }
+    } else if result.is::<ScriptFile>() {
+        outputs.push(Output::File(Box::new(result.cast::<ScriptFile>().into_file())));
+    } else {
+        outputs.push(Output::Value(dynamic_to_value(result)?));
+    }
+    Ok(())
+}
+
+fn to_dynamic(result: FileOrValue, mapper: OutputMapper, variant: &FileVariant) -> Dynamic {
+    match result {
+        FileOrValue::File(file) => {
+            Dynamic::from(ScriptFile::new(file.clone(), mapper, variant.clone()))
+        }
+        FileOrValue::Value(value) => value_to_dynamic(value),
+    }
+}
+
+/// Paths containing wildcards result in a list of all matches,
+/// while other paths result in the first match, or `()` if there is none.
+fn collect_results<I>(path: &str, mut results: I) -> ScriptResult<Dynamic>
+where
+    I: Iterator<Item = Result<Dynamic>>,
+{
+    let result = if PathIter::new(path).any(|c| !matches!(c, PathComponent::Name(_))) {
+        results.collect::<Result<Array>>().map(Dynamic::from_array)
+    } else {
+        results.next().transpose().map(|d| d.unwrap_or(Dynamic::UNIT))
+    };
+    result.map_err(|err| format!("{:#}", err).into())
+}
+
+pub fn value_to_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::UNIT,
+        Value::String(s) => Dynamic::from(s.clone()),
+        Value::Boolean(b) => Dynamic::from_bool(*b),
+        Value::Integer(x) => Dynamic::from_int(*x),
+        Value::Float(x) => Dynamic::from_float(*x),
+        // rendered in a format which sorts chronologically, as long as the offsets are equal
+        Value::DateTime(d) => Dynamic::from(d.to_string()),
+        Value::Sequence(seq) => Dynamic::from_array(seq.iter().map(value_to_dynamic).collect()),
+        Value::Mapping(map) => Dynamic::from_map(
+            map.iter()
+                .map(|(k, v)| (k.as_str().into(), value_to_dynamic(v)))
+                .collect(),
+        ),
+    }
+}
+
+pub fn dynamic_to_value(value: Dynamic) -> Result<Value> {
+    let value = if value.is_unit() {
+        Value::Null
+    } else if value.is::<bool>() {
+        Value::Boolean(value.cast::<bool>())
+    } else if value.is::<INT>() {
+        Value::from(value.cast::<INT>())
+    } else if value.is::<FLOAT>() {
+        Value::Float(value.cast::<FLOAT>())
+    } else if value.is::<char>() {
+        Value::String(value.cast::<char>().to_string())
+    } else if value.is_string() {
+        Value::String(value.into_string().unwrap())
+    } else if value.is_array() {
+        let seq: Result<Vec<Value>> = value
+            .cast::<Array>()
+            .into_iter()
+            .map(dynamic_to_value)
+            .collect();
+        Value::Sequence(seq?)
+    } else if value.is_map() {
+        let map: Result<IndexMap<String, Value>> = value
+            .cast::<Map>()
+            .into_iter()
+            .map(|(k, v)| dynamic_to_value(v).map(|v| (k.to_string(), v)))
+            .collect();
+        Value::Mapping(map?)
+    } else {
+        return Err(anyhow!(
+            "script value of type '{}' cannot be used as a primitive value",
+            value.type_name()
+        ));
+    };
+    Ok(value)
+}
+
+/// Set the value at the given (wildcard free) path,
+/// creating the intermediate mappings where needed.
+fn set_value(root: &mut Value, path: &str, value: Value) -> Result<()> {
+    let mut names = Vec::new();
+    for component in PathIter::new(path) {
+        match component {
+            PathComponent::Name(name) => names.push(name.to_lowercase()),
+            _ => {
+                return Err(anyhow!(
+                    "cannot set metadata using wildcard path '{}'",
+                    path
+                ))
+            }
+        }
+    }
+    let (last, names) = match names.split_last() {
+        Some(split) => split,
+        None => {
+            *root = value;
+            return Ok(());
+        }
+    };
+    let mut current = root;
+    for name in names {
+        current = mapping_entry(current, path)?
+            .entry(name.clone())
+            .or_insert(Value::Null);
+    }
+    mapping_entry(current, path)?.insert(last.clone(), value);
+    Ok(())
+}
+
+fn mapping_entry<'a>(value: &'a mut Value, path: &str) -> Result<&'a mut IndexMap<String, Value>> {
+    if let Value::Null = value {
+        *value = Value::Mapping(IndexMap::new());
+    }
+    match value {
+        Value::Mapping(map) => Ok(map),
+        _ => Err(anyhow!(
+            "cannot set metadata '{}' within a non-mapping value",
+            path
+        )),
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::io::{Config, FileFormat, FileInfo};
+
+mod templates {
+    /// A file of a template, as a pair of path (relative to the workspace root) and content.
+    pub type TemplateFile = (&'static str, &'static [u8]);
+    /// A template, as a pair of name and all of its files.
+    pub type Template = (&'static str, &'static [TemplateFile]);
+
+    // generated by the build script from the example workspaces
+    include!(concat!(env!("OUT_DIR"), "/templates.rs"));
+}
+
+/// Template used for new workspaces unless specified otherwise.
+pub const DEFAULT_TEMPLATE: &str = "basic";
+
+/// Names of all templates bundled with TSG.
+pub fn templates() -> impl Iterator<Item = &'static str> {
+    templates::TEMPLATES.iter().map(|(name, _)| *name)
+}
+
+/// Create a new workspace in the given directory using the given template,
+/// returning the paths of all files created.
+///
+/// The directory has to be empty or not exist yet.
+pub fn new_workspace<P: AsRef<Path>>(dir: P, template: &str) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let files = templates::TEMPLATES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(template))
+        .map(|(_, files)| *files)
+        .ok_or_else(|| {
+            anyhow!(
+                "unknown template '{}', expected one of: {}",
+                template,
+                templates().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+        return Err(anyhow!("{} already exists and is not empty", dir.display()));
+    }
+    let mut paths = Vec::with_capacity(files.len());
+    for (path, content) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content).with_context(|| format!("write {}", path.display()))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Add a new page at the given path, relative to the `pages` directory of the workspace,
+/// with stubs for its front matter. Returns the path of the page created.
+///
+/// The path may name a variant of a page, using the dimensions configured for the workspace.
+pub fn new_page<P: AsRef<Path>>(root: P, page: &str) -> Result<PathBuf> {
+    let config = Config::read(&root)?;
+    let page = page.trim_start_matches(['/', '\\']);
+    let page = page.strip_prefix("pages/").unwrap_or(page);
+    let path = root.as_ref().join("pages").join(page);
+    let info = FileInfo::in_workspace(root.as_ref(), &path, &config)
+        .with_context(|| format!("invalid page path '{}'", page))?;
+    if path.exists() {
+        return Err(anyhow!("page {} already exists", path.display()));
+    }
+    let title = title(info.name());
+    let content = match info.format() {
+        FileFormat::Markdown => format!("---\ntitle: {}\n---\n\n# {}\n", title, title),
+        FileFormat::Html => format!("<!--\ntitle: {}\n-->\n<h1>{}</h1>\n", title, title),
+        FileFormat::Rhai => format!(
+            "// pages generated by scripts define their metadata using `generator.html`\n`<h1>{}</h1>`\n",
+            title
+        ),
+        _ => return Err(anyhow!("pages have to be HTML, Markdown or Rhai files")),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content).with_context(|| format!("write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Title for a page with the given file name, e.g. `hello-world` becomes `Hello world`.
+fn title(name: &str) -> String {
+    let words = name.replace(['-', '_'], " ");
+    let mut chars = words.trim().chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::publish::{build, BuildOptions};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_new_workspace() {
+        for template in templates() {
+            let dir = TempDir::new(template);
+            let files = new_workspace(&dir, template).unwrap();
+            assert!(!files.is_empty());
+            let report = build(&BuildOptions::new(&dir, dir.join("publish"))).unwrap();
+            assert!(!report.pages.is_empty(), "template: {}", template);
+            assert!(new_workspace(&dir, template).is_err());
+        }
+        assert!(new_workspace(TempDir::new("new-unknown"), "unknown").is_err());
+    }
+
+    #[test]
+    fn test_new_page() {
+        let root = TempDir::new("new-page");
+        let path = new_page(&root, "foo/hello-world.md").unwrap();
+        assert_eq!(path, root.join("pages/foo/hello-world.md"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "---\ntitle: Hello world\n---\n\n# Hello world\n"
+        );
+        assert!(new_page(&root, "foo/hello-world.md").is_err());
+        assert!(new_page(&root, "data.yml").is_err());
+        assert!(new_page(&root, "pages/about.html")
+            .unwrap()
+            .ends_with("pages/about.html"));
+    }
+
+    #[test]
+    fn test_new_page_variant() {
+        let root = TempDir::new("new-page-variant");
+        assert!(new_page(&root, "about.accessible.md").is_err());
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("tsg.yml"), "variants:\n  theme: [accessible]\n").unwrap();
+        let path = new_page(&root, "about.accessible.md").unwrap();
+        assert_eq!(path, root.join("pages/about.accessible.md"));
+        assert!(fs::read_to_string(&path).unwrap().contains("title: About\n"));
+        assert!(new_page(&root, "about.nl.accessible.md").is_ok());
+    }
+}
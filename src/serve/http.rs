@@ -0,0 +1,172 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::publish::INDEX_FILE;
+
+/// Method and path of an HTTP request, the only parts of a request used by the server.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+}
+
+/// Read the request line and headers of an HTTP request.
+pub fn read_request(stream: &TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream.take(16 * 1024));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+        _ => return Err(anyhow!("invalid request line '{}'", line.trim())),
+    };
+    // skip the headers, the server does not need any of them
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+    Ok(Request {
+        method,
+        path: percent_decode(path),
+    })
+}
+
+pub fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(stream.flush()?)
+}
+
+/// The file within the publish directory served for the given URL path, if any,
+/// where directories are served using their index file and `/foo` can refer to `/foo.html`.
+pub fn resolve_path(out_dir: &Path, url_path: &str) -> Option<PathBuf> {
+    let mut path = out_dir.to_path_buf();
+    for component in Path::new(url_path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(name) => path.push(name),
+            Component::CurDir => (),
+            // never serve anything outside of the publish directory
+            _ => return None,
+        }
+    }
+    if path.is_dir() {
+        path.push(INDEX_FILE);
+    }
+    if path.is_file() {
+        return Some(path);
+    }
+    let html = path.with_extension("html");
+    if path.extension().is_none() && html.is_file() {
+        return Some(html);
+    }
+    None
+}
+
+pub fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "pdf" => "application/pdf",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_resolve_path() {
+        let out = TempDir::new("http");
+        fs::create_dir_all(out.join("blog")).unwrap();
+        fs::write(out.join("index.html"), "").unwrap();
+        fs::write(out.join("blog/index.html"), "").unwrap();
+        fs::write(out.join("about.html"), "").unwrap();
+        fs::write(out.join("main css.css"), "").unwrap();
+
+        let test_cases = vec![
+            ("/", Some("index.html")),
+            ("/blog", Some("blog/index.html")),
+            ("/blog/", Some("blog/index.html")),
+            ("/about", Some("about.html")),
+            ("/about.html", Some("about.html")),
+            ("/main%20css.css", Some("main css.css")),
+            ("/missing", None),
+            ("/../index.html", None),
+        ];
+        for (url, expected) in test_cases {
+            let path = resolve_path(&out, &percent_decode(url));
+            assert_eq!(path, expected.map(|p| out.join(p)), "url: {}", url);
+        }
+    }
+}
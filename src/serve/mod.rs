@@ -0,0 +1,222 @@
+use std::fs;
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::publish::{self, BuildOptions, BuildReport};
+
+mod http;
+
+mod watch;
+pub use watch::{Snapshot, WATCHED_DIRS};
+
+/// Path of the Server-Sent Events stream used by served pages to reload on changes.
+pub const EVENTS_PATH: &str = "/__tsg/events";
+
+/// Default interval at which the workspace is polled for changes.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// comments sent over idle event streams, such that closed connections get detected
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Options of the local development server.
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub build: BuildOptions,
+    pub address: SocketAddr,
+    pub poll_interval: Duration,
+}
+
+/// Result of the last build, shared between the watcher and all connections.
+#[derive(Default)]
+struct State {
+    status: Mutex<Status>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Status {
+    // incremented for every build
+    version: u64,
+    error: Option<String>,
+}
+
+/// Build the workspace and serve the publish directory,
+/// rebuilding and reloading all open pages whenever the workspace changes.
+///
+/// Only returns in case the server could not be started.
+pub fn serve<F>(options: ServeOptions, log: F) -> Result<()>
+where
+    F: Fn(&str) + Send + 'static,
+{
+    let listener = TcpListener::bind(options.address)
+        .with_context(|| format!("listen on {}", options.address))?;
+    let state = Arc::new(State::default());
+
+    rebuild(&options.build, &state, &log);
+    log(&format!(
+        "serving {} at http://{}",
+        options.build.out_dir.display(),
+        listener.local_addr()?
+    ));
+    {
+        let options = options.clone();
+        let state = state.clone();
+        thread::spawn(move || watch(&options, &state, &log));
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let out_dir = options.build.out_dir.clone();
+        let state = state.clone();
+        thread::spawn(move || {
+            // errors are due to the browser closing its connection
+            let _ = handle(stream, &out_dir, &state);
+        });
+    }
+    Ok(())
+}
+
+fn watch<F: Fn(&str)>(options: &ServeOptions, state: &State, log: &F) {
+    let mut snapshot = Snapshot::take(&options.build.root);
+    loop {
+        thread::sleep(options.poll_interval);
+        let next = Snapshot::take(&options.build.root);
+        if next != snapshot {
+            snapshot = next;
+            rebuild(&options.build, state, log);
+        }
+    }
+}
+
+fn rebuild<F: Fn(&str)>(options: &BuildOptions, state: &State, log: &F) {
+    let result = publish::build(options);
+    let mut status = state.status.lock().unwrap();
+    status.version += 1;
+    status.error = match result {
+        Ok(BuildReport { pages, .. }) => {
+            log(&format!("built {} page(s)", pages.len()));
+            None
+        }
+        Err(err) => {
+            let err = format!("{:#}", err);
+            log(&format!("error: {}", err));
+            Some(err)
+        }
+    };
+    state.changed.notify_all();
+}
+
+fn handle(mut stream: TcpStream, out_dir: &Path, state: &State) -> Result<()> {
+    let request = http::read_request(&stream)?;
+    if request.method != "GET" {
+        return http::write_response(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"method not allowed",
+        );
+    }
+    if request.path == EVENTS_PATH {
+        return stream_events(stream, state);
+    }
+    let error = state.status.lock().unwrap().error.clone();
+    match http::resolve_path(out_dir, &request.path) {
+        Some(path) => {
+            let content_type = http::content_type(&path);
+            let mut body = fs::read(&path)?;
+            if content_type.starts_with("text/html") {
+                body = inject_client(&body, error.as_deref());
+            }
+            http::write_response(&mut stream, "200 OK", content_type, &body)
+        }
+        None => {
+            let body = inject_client(b"<h1>404 Not Found</h1>", error.as_deref());
+            http::write_response(
+                &mut stream,
+                "404 Not Found",
+                "text/html; charset=utf-8",
+                &body,
+            )
+        }
+    }
+}
+
+/// Send a reload event for every build, until the connection is closed.
+fn stream_events(mut stream: TcpStream, state: &State) -> Result<()> {
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\n\r\n",
+    )?;
+    stream.flush()?;
+    let mut version = state.status.lock().unwrap().version;
+    loop {
+        let status = state.status.lock().unwrap();
+        let (status, _) = state
+            .changed
+            .wait_timeout_while(status, KEEP_ALIVE_INTERVAL, |s| s.version == version)
+            .unwrap();
+        let message: &[u8] = if status.version != version {
+            version = status.version;
+            b"data: reload\n\n"
+        } else {
+            b": keep-alive\n\n"
+        };
+        drop(status);
+        stream.write_all(message)?;
+        stream.flush()?;
+    }
+}
+
+/// Add the live reload script to the HTML page, as well as
+/// an overlay showing the build error, if any, at the end of its body.
+fn inject_client(html: &[u8], error: Option<&str>) -> Vec<u8> {
+    let mut client = format!(
+        "<script>new EventSource(\"{}\").onmessage = () => location.reload();</script>",
+        EVENTS_PATH
+    );
+    if let Some(error) = error {
+        client.push_str(&format!(
+            "<div id=\"tsg-error\" style=\"position: fixed; inset: 0; z-index: 2147483647; overflow: auto; \
+             padding: 2em; background: rgba(20, 20, 20, 0.95); color: #ff8080; \
+             font: 14px/1.5 monospace; white-space: pre-wrap;\"><strong>tsg: build failed</strong>\n\n{}</div>",
+            http::escape_html(error)
+        ));
+    }
+    let html = String::from_utf8_lossy(html);
+    let mut output = html.to_string();
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => output.insert_str(index, &client),
+        None => output.push_str(&client),
+    }
+    output.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inject_client() {
+        let html =
+            String::from_utf8(inject_client(b"<html><body><p>hi</p></BODY></html>", None)).unwrap();
+        assert!(html.starts_with("<html><body><p>hi</p><script>new EventSource(\"/__tsg/events\")"));
+        assert!(html.ends_with("</script></BODY></html>"));
+        assert!(!html.contains("tsg-error"));
+
+        let html = String::from_utf8(inject_client(
+            b"<p>hi</p>",
+            Some("include 'a<b>' not found"),
+        ))
+        .unwrap();
+        assert!(html.starts_with("<p>hi</p><script>"));
+        assert!(html.contains("tsg: build failed</strong>\n\ninclude 'a&lt;b&gt;' not found</div>"));
+    }
+}
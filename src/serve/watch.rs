@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::io::CONFIG_FILE;
+
+/// Directories of a workspace which are watched for changes.
+pub const WATCHED_DIRS: [&str; 4] = ["pages", "layouts", "includes", "assets"];
+
+/// State of all files within the watched directories of a workspace,
+/// as well as of its configuration file, used to detect changes by polling.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    files: BTreeMap<PathBuf, (Option<SystemTime>, u64)>,
+}
+
+impl Snapshot {
+    pub fn take<P: AsRef<Path>>(root: P) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        for dir in WATCHED_DIRS {
+            snapshot.add_dir(&root.as_ref().join(dir));
+        }
+        let config = root.as_ref().join(CONFIG_FILE);
+        if let Ok(meta) = fs::metadata(&config) {
+            snapshot.files.insert(config, (meta.modified().ok(), meta.len()));
+        }
+        snapshot
+    }
+
+    fn add_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            // a directory removed while walking is picked up by the next snapshot
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.metadata() {
+                Ok(meta) if meta.is_dir() => self.add_dir(&path),
+                Ok(meta) => {
+                    self.files.insert(path, (meta.modified().ok(), meta.len()));
+                }
+                Err(_) => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_snapshot() {
+        let root = TempDir::new("watch");
+        fs::create_dir_all(root.join("pages/blog")).unwrap();
+        fs::write(root.join("pages/blog/post.md"), "# Post").unwrap();
+
+        let snapshot = Snapshot::take(&root);
+        assert_eq!(snapshot, Snapshot::take(&root));
+
+        fs::write(root.join("pages/blog/post.md"), "# Updated post").unwrap();
+        assert_ne!(snapshot, Snapshot::take(&root));
+
+        let snapshot = Snapshot::take(&root);
+        fs::write(root.join("public.html"), "not watched").unwrap();
+        assert_eq!(snapshot, Snapshot::take(&root));
+        fs::create_dir_all(root.join("assets")).unwrap();
+        fs::write(root.join("assets/main.css"), "").unwrap();
+        assert_ne!(snapshot, Snapshot::take(&root));
+
+        let snapshot = Snapshot::take(&root);
+        fs::write(root.join(CONFIG_FILE), "fallbacks: {}").unwrap();
+        assert_ne!(snapshot, Snapshot::take(&root));
+    }
+}